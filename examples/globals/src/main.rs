@@ -1,21 +1,12 @@
 use ndata;
-use ndata::dataobject::DataObject;
+use ndata::globals;
 
 fn main() {
-  init();
+  ndata::init_with_globals();
   do_a_thing();
   print_result();
 }
 
-fn init() {
-  ndata::init();
-  DataObject::new().incr();
-}
-
-fn globals() -> DataObject {
-  DataObject::get(0)
-}
-
 fn do_a_thing() {
   globals().put_string("result", "Hello, world!");
 }
@@ -2,6 +2,9 @@ extern crate alloc;
 use crate::dataobject::*;
 use crate::dataarray::*;
 use crate::databytes::*;
+use crate::ndataerror::*;
+use core::fmt;
+use core::cmp::Ordering;
 
 #[cfg(feature="no_std_support")]
 use alloc::string::String;
@@ -10,6 +13,11 @@ use alloc::borrow::ToOwned;
 #[cfg(feature="no_std_support")]
 use alloc::string::ToString;
 
+/// FNV-1a offset basis, used by ```Data::content_hash()```
+const FNV_OFFSET:u64 = 0xcbf29ce484222325;
+/// FNV-1a prime, used by ```Data::content_hash()```
+const FNV_PRIME:u64 = 0x100000001b3;
+
 /// Represents an NData value
 ///
 /// DObject, DArray, and DBytes are considered *instances* and the clone() function will return a reference to the *same* instance.
@@ -99,6 +107,18 @@ impl Clone for Data{
 }
 
 impl Data {
+  /// Returns a deep copy of the value.
+  ///
+  /// Unlike ```clone()```, DObject, DArray, and DBytes values are copied to *new* instances 
+  /// using the type's ```deep_copy()``` function. The new instance's reference is held by the
+  /// returned value only until the next ```gc()```, so store it in an object or array first.
+  pub fn deep_clone(&self) -> Data {
+    if let Data::DObject(i) = self { return Data::DObject(DataObject::get(*i).deep_copy().data_ref); }
+    if let Data::DArray(i) = self { return Data::DArray(DataArray::get(*i).deep_copy().data_ref); }
+    if let Data::DBytes(i) = self { return Data::DBytes(DataBytes::get(*i).deep_copy().data_ref); }
+    self.clone()
+  }
+  
   /// Returns ```true``` if the value is of type ```DInt``` or ```DFloat```.
   pub fn is_number(&self) -> bool {
     self.is_int() || self.is_float()
@@ -182,6 +202,124 @@ impl Data {
     if let Data::DBytes(i) = self { DataBytes::get(*i) } else { panic!("Not a byte array: {:?}/{}", self, Data::as_string(self.clone())); }
   }
   
+  /// Returns the value as an ```f64``` if it is a number or a string containing one. Strings
+  /// are trimmed of surrounding whitespace and parsed with ```str::parse::<f64>()```.
+  pub(crate) fn to_float_lenient(&self) -> Option<f64> {
+    match self {
+      Data::DFloat(f) => Some(*f),
+      Data::DInt(i) => Some(*i as f64),
+      Data::DString(s) => s.trim().parse::<f64>().ok(),
+      _ => None,
+    }
+  }
+  
+  /// Returns the sum of two numeric values, or ```None``` if either value is not a number or 
+  /// the result overflows. Two ```DInt``` values produce a ```DInt```, otherwise the values are 
+  /// added as ```f64``` and a non-finite result counts as overflow.
+  pub fn checked_add(&self, b:&Data) -> Option<Data> {
+    self.checked_op(b, i64::checked_add, |x, y| x + y)
+  }
+
+  /// Returns the difference of two numeric values, as for ```checked_add()```.
+  pub fn checked_sub(&self, b:&Data) -> Option<Data> {
+    self.checked_op(b, i64::checked_sub, |x, y| x - y)
+  }
+
+  /// Returns the product of two numeric values, as for ```checked_add()```.
+  pub fn checked_mul(&self, b:&Data) -> Option<Data> {
+    self.checked_op(b, i64::checked_mul, |x, y| x * y)
+  }
+
+  /// Returns the quotient of two numeric values, as for ```checked_add()```. Division by zero
+  /// returns ```None```.
+  pub fn checked_div(&self, b:&Data) -> Option<Data> {
+    self.checked_op(b, i64::checked_div, |x, y| x / y)
+  }
+
+  fn checked_op(&self, b:&Data, fi:fn(i64, i64) -> Option<i64>, ff:fn(f64, f64) -> f64) -> Option<Data> {
+    match (self, b) {
+      (Data::DInt(x), Data::DInt(y)) => fi(*x, *y).map(Data::DInt),
+      _ => {
+        let x = if let Data::DInt(i) = self { *i as f64 } else if let Data::DFloat(f) = self { *f } else { return None; };
+        let y = if let Data::DInt(i) = b { *i as f64 } else if let Data::DFloat(f) = b { *f } else { return None; };
+        let z = ff(x, y);
+        if z.is_finite() { Some(Data::DFloat(z)) } else { None }
+      },
+    }
+  }
+
+  /// Returns the name of the type of the value, e.g. ```"int"``` or ```"object"```.
+  pub fn type_name(&self) -> &'static str {
+    match self {
+      Data::DObject(_) => "object",
+      Data::DArray(_) => "array",
+      Data::DBytes(_) => "bytes",
+      Data::DString(_) => "string",
+      Data::DBoolean(_) => "boolean",
+      Data::DFloat(_) => "float",
+      Data::DInt(_) => "int",
+      Data::DNull => "null",
+    }
+  }
+
+  fn wrong_type(&self, expected:&'static str) -> NDataError {
+    NDataError::WrongDataType { expected, found: self.type_name() }
+  }
+
+  /// Returns the underlying ```i64``` value, or an error if not ```DInt```.
+  pub fn try_int(&self) -> Result<i64, NDataError> {
+    if let Data::DInt(i) = self { Ok(*i) } else { Err(self.wrong_type("int")) }
+  }
+
+  /// Returns the underlying ```f64``` value, or an error if not ```DFloat```.
+  pub fn try_float(&self) -> Result<f64, NDataError> {
+    if let Data::DFloat(f) = self { Ok(*f) } else { Err(self.wrong_type("float")) }
+  }
+
+  /// Returns the underlying ```bool``` value, or an error if not ```DBoolean```.
+  pub fn try_boolean(&self) -> Result<bool, NDataError> {
+    if let Data::DBoolean(b) = self { Ok(*b) } else { Err(self.wrong_type("boolean")) }
+  }
+
+  /// Returns the underlying ```String``` value, or an error if not ```DString```.
+  pub fn try_string(&self) -> Result<String, NDataError> {
+    if let Data::DString(s) = self { Ok(s.to_owned()) } else { Err(self.wrong_type("string")) }
+  }
+
+  /// Returns a new ```DataObject``` representing the underlying object instance, 
+  /// or an error if not ```DObject```.
+  pub fn try_object(&self) -> Result<DataObject, NDataError> {
+    if let Data::DObject(i) = self { Ok(DataObject::get(*i)) } else { Err(self.wrong_type("object")) }
+  }
+
+  /// Returns a new ```DataArray``` representing the underlying array instance, 
+  /// or an error if not ```DArray```.
+  pub fn try_array(&self) -> Result<DataArray, NDataError> {
+    if let Data::DArray(i) = self { Ok(DataArray::get(*i)) } else { Err(self.wrong_type("array")) }
+  }
+
+  /// Returns a new ```DataBytes``` representing the underlying byte buffer instance, 
+  /// or an error if not ```DBytes```.
+  pub fn try_bytes(&self) -> Result<DataBytes, NDataError> {
+    if let Data::DBytes(i) = self { Ok(DataBytes::get(*i)) } else { Err(self.wrong_type("bytes")) }
+  }
+
+  /// Increase the reference count of the instance this value points to, if any. The heap 
+  /// holding that instance must not already be locked by the caller.
+  pub(crate) fn incr_ref(&self) {
+    if let Data::DObject(i) = self { oheap().lock().incr(*i); }
+    else if let Data::DArray(i) = self { aheap().lock().incr(*i); }
+    else if let Data::DBytes(i) = self { bheap().lock().incr(*i); }
+  }
+
+  /// Queue a reduction of the reference count of the instance this value points to, if any.
+  /// The instance remains on the heap until the next ```gc()```.
+  pub(crate) fn queue_decr(&self) {
+    if let Data::DObject(i) = self { let _x = DataObject { data_ref: *i }; }
+    else if let Data::DArray(i) = self { let _x = DataArray { data_ref: *i }; }
+    else if let Data::DBytes(i) = self { let _x = DataBytes { data_ref: *i }; }
+  }
+
   /// Returns a ```String``` representation of the underlying value.
   pub fn as_string(a:Data) -> String {
     if a.is_float() { return a.float().to_string(); }
@@ -195,6 +333,213 @@ impl Data {
     "".to_string()
   }
   
+  /// Returns the approximate number of bytes of heap memory used by this value, not counting
+  /// the ```Data``` itself: the capacity of a string, the length of a byte buffer, and for 
+  /// objects and arrays the size of their entries plus the ```heap_size()``` of each value. 
+  /// Instances referenced more than once are only counted once. Hash table overhead is not 
+  /// included.
+  pub fn heap_size(&self) -> usize {
+    self.heap_size_in(&mut Vec::new())
+  }
+  
+  fn heap_size_in(&self, seen:&mut Vec<Data>) -> usize {
+    if let Data::DString(s) = self { return s.capacity(); }
+    if !(self.is_object() || self.is_array() || self.is_bytes()) { return 0; }
+    if seen.iter().any(|d| Data::equals(d.clone(), self.clone())) { return 0; }
+    seen.push(self.clone());
+    let mut n = 0;
+    if let Data::DObject(i) = self {
+      for (k, v) in DataObject::get(*i).objects() {
+        n += core::mem::size_of::<String>() + k.len() + core::mem::size_of::<Data>() + v.heap_size_in(seen);
+      }
+    }
+    else if let Data::DArray(i) = self {
+      let (size, values) = {
+        let heap = &mut aheap().lock();
+        let vec = heap.get(*i);
+        (vec.storage_size(), vec.instances().to_vec())
+      };
+      n = size;
+      for v in values {
+        n += v.heap_size_in(seen);
+      }
+    }
+    else if let Data::DBytes(i) = self {
+      n = bheap().lock().get(*i).current_len();
+    }
+    n
+  }
+  
+  /// Write the contents of this value for ```Debug``` output, with object keys in sorted order.
+  /// Instances whose heap is locked or not initialized, or that are no longer on the heap, are
+  /// written as their type and data_ref. ```path``` holds the objects (```true```) and arrays
+  /// (```false```) currently being written, so that cycles are written the same way.
+  pub(crate) fn debug_contents(&self, f:&mut fmt::Formatter<'_>, path:&mut Vec<(bool, usize)>) -> fmt::Result {
+    match self {
+      Data::DObject(i) => {
+        let entries = match oheap().try_lock_if_initialized() {
+          Some(mut heap) if !path.contains(&(true, *i)) => heap.try_get(*i).map(|map| {
+            let mut v: Vec<(String, Data)> = map.iter().map(|(k, v)| (k.to_string(), v.clone())).collect();
+            v.sort_by(|a, b| a.0.cmp(&b.0));
+            v
+          }),
+          _ => None,
+        };
+        let Some(entries) = entries else { return write!(f, "<object {}>", i); };
+        path.push((true, *i));
+        write!(f, "{{")?;
+        for (n, (k, v)) in entries.iter().enumerate() {
+          if n > 0 { write!(f, ", ")?; }
+          write!(f, "{:?}: ", k)?;
+          v.debug_contents(f, path)?;
+        }
+        path.pop();
+        write!(f, "}}")
+      },
+      Data::DArray(i) => {
+        let values = match aheap().try_lock_if_initialized() {
+          Some(mut heap) if !path.contains(&(false, *i)) => heap.try_get(*i).map(|vec| vec.to_vec()),
+          _ => None,
+        };
+        let Some(values) = values else { return write!(f, "<array {}>", i); };
+        path.push((false, *i));
+        write!(f, "[")?;
+        for (n, v) in values.iter().enumerate() {
+          if n > 0 { write!(f, ", ")?; }
+          v.debug_contents(f, path)?;
+        }
+        path.pop();
+        write!(f, "]")
+      },
+      Data::DBytes(i) => {
+        let len = match bheap().try_lock_if_initialized() {
+          Some(mut heap) => heap.try_get(*i).map(|stream| stream.current_len()),
+          None => None,
+        };
+        match len {
+          Some(len) => write!(f, "<{} bytes>", len),
+          None => write!(f, "<bytes {}>", i),
+        }
+      },
+      Data::DString(s) => write!(f, "{:?}", s),
+      Data::DBoolean(b) => write!(f, "{}", b),
+      Data::DFloat(x) => write!(f, "{:?}", x),
+      Data::DInt(x) => write!(f, "{}", x),
+      Data::DNull => write!(f, "null"),
+    }
+  }
+  
+  /// Returns a 64-bit FNV-1a hash of the content of this value. Objects are hashed by their keys
+  /// in sorted order and their values, arrays by their values in order, and byte buffers by their
+  /// current bytes, recursively, so values for which ```deep_equals()``` is ```true``` have the
+  /// same hash no matter which instances hold them. A reference back to an object or array that contains it
+  /// is hashed by how many levels up it points.
+  pub fn content_hash(&self) -> u64 {
+    let mut h = FNV_OFFSET;
+    self.content_hash_in(&mut h, &mut Vec::new());
+    h
+  }
+  
+  fn content_hash_in(&self, h:&mut u64, path:&mut Vec<(bool, usize)>) {
+    fn fold(h:&mut u64, bytes:&[u8]) {
+      for b in bytes {
+        *h ^= *b as u64;
+        *h = h.wrapping_mul(FNV_PRIME);
+      }
+    }
+    let node = match self {
+      Data::DObject(i) => Some((true, *i)),
+      Data::DArray(i) => Some((false, *i)),
+      _ => None,
+    };
+    if let Some(node) = node {
+      if let Some(pos) = path.iter().position(|n| *n == node) {
+        fold(h, &[7]);
+        fold(h, &((path.len() - pos) as u64).to_le_bytes());
+        return;
+      }
+    }
+    fold(h, &[self.type_rank()]);
+    match self {
+      Data::DObject(i) => {
+        let mut entries = DataObject::get(*i).objects();
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        fold(h, &(entries.len() as u64).to_le_bytes());
+        path.push((true, *i));
+        for (k, v) in entries {
+          fold(h, &(k.len() as u64).to_le_bytes());
+          fold(h, k.as_bytes());
+          v.content_hash_in(h, path);
+        }
+        path.pop();
+      },
+      Data::DArray(i) => {
+        let values = DataArray::get(*i).objects();
+        fold(h, &(values.len() as u64).to_le_bytes());
+        path.push((false, *i));
+        for v in values {
+          v.content_hash_in(h, path);
+        }
+        path.pop();
+      },
+      Data::DBytes(i) => {
+        let bytes = DataBytes::get(*i).get_data();
+        fold(h, &(bytes.len() as u64).to_le_bytes());
+        fold(h, &bytes);
+      },
+      Data::DString(s) => {
+        fold(h, &(s.len() as u64).to_le_bytes());
+        fold(h, s.as_bytes());
+      },
+      Data::DBoolean(b) => fold(h, &[*b as u8]),
+      // Ints and floats share a type rank but are never structurally equal. Adding 0.0 turns
+      // -0.0 into 0.0, which compares equal to it.
+      Data::DFloat(x) => { fold(h, &[1]); fold(h, &(*x + 0.0).to_bits().to_le_bytes()); },
+      Data::DInt(x) => { fold(h, &[0]); fold(h, &x.to_le_bytes()); },
+      Data::DNull => {},
+    }
+  }
+  
+  /// Returns ```true``` if the two values are structurally equal. Objects are compared by their
+  /// keys and values and arrays by their elements in order, recursively, and byte buffers by 
+  /// their current bytes. Instances with the same data_ref are equal without being compared, and 
+  /// cyclic structures are supported. Other values are compared as for ```equals()```, which
+  /// compares instances by data_ref only.
+  pub fn deep_equals(a:&Data, b:&Data) -> bool {
+    let mut seen = Vec::<(bool, usize, usize)>::new();
+    Data::deep_equals_in(a, b, &mut seen)
+  }
+  
+  /// Compare two values structurally, treating pairs of objects or arrays already being compared
+  /// as equal so that cyclic structures terminate.
+  fn deep_equals_in(a:&Data, b:&Data, seen:&mut Vec<(bool, usize, usize)>) -> bool {
+    match (a, b) {
+      (Data::DObject(i), Data::DObject(j)) => {
+        if i == j || seen.contains(&(true, *i, *j)) { return true; }
+        seen.push((true, *i, *j));
+        let x = DataObject::get(*i).objects();
+        let y = DataObject::get(*j);
+        if x.len() != y.objects().len() { return false; }
+        for (k, v) in x {
+          match y.try_get_property(&k) {
+            Ok(w) => if !Data::deep_equals_in(&v, &w, seen) { return false; },
+            Err(_) => return false,
+          }
+        }
+        true
+      },
+      (Data::DArray(i), Data::DArray(j)) => {
+        if i == j || seen.contains(&(false, *i, *j)) { return true; }
+        seen.push((false, *i, *j));
+        let x = DataArray::get(*i).objects();
+        let y = DataArray::get(*j).objects();
+        x.len() == y.len() && x.iter().zip(y.iter()).all(|(v, w)| Data::deep_equals_in(v, w, seen))
+      },
+      (Data::DBytes(i), Data::DBytes(j)) => i == j || DataBytes::get(*i).get_data() == DataBytes::get(*j).get_data(),
+      _ => Data::equals(a.clone(), b.clone()),
+    }
+  }
+  
   // Return true if the two Data structs are equal
   pub fn equals(a:Data, b:Data) -> bool {
     if a.is_float() { if b.is_float() { return a.float() == b.float(); } }
@@ -207,6 +552,50 @@ impl Data {
     else if a.is_null() { return b.is_null(); }
     false
   }
+  
+  /// Compare two values in a deterministic order across types:
+  /// null < boolean < number < string < array < object < bytes.
+  ///
+  /// Ints and floats are compared numerically, with an int sorting before an equal float, and 
+  /// ```false``` sorts before ```true```. ```NaN``` is greater than every other number and equal 
+  /// to itself, so it sorts last among numbers. Strings compare by their UTF-8 bytes. Arrays, 
+  /// objects and byte buffers are compared by ```data_ref```, not by content, so their order is 
+  /// stable but arbitrary.
+  pub fn compare(a:&Data, b:&Data) -> Ordering {
+    match (a, b) {
+      (Data::DInt(x), Data::DInt(y)) => x.cmp(y),
+      (Data::DInt(x), Data::DFloat(y)) => Data::compare_floats(*x as f64, *y).then(Ordering::Less),
+      (Data::DFloat(x), Data::DInt(y)) => Data::compare_floats(*x, *y as f64).then(Ordering::Greater),
+      (Data::DFloat(x), Data::DFloat(y)) => Data::compare_floats(*x, *y),
+      (Data::DBoolean(x), Data::DBoolean(y)) => x.cmp(y),
+      (Data::DString(x), Data::DString(y)) => x.cmp(y),
+      (Data::DArray(x), Data::DArray(y)) => x.cmp(y),
+      (Data::DObject(x), Data::DObject(y)) => x.cmp(y),
+      (Data::DBytes(x), Data::DBytes(y)) => x.cmp(y),
+      _ => a.type_rank().cmp(&b.type_rank()),
+    }
+  }
+  
+  fn compare_floats(x:f64, y:f64) -> Ordering {
+    match (x.is_nan(), y.is_nan()) {
+      (true, true) => Ordering::Equal,
+      (true, false) => Ordering::Greater,
+      (false, true) => Ordering::Less,
+      (false, false) => x.partial_cmp(&y).unwrap(),
+    }
+  }
+  
+  fn type_rank(&self) -> u8 {
+    match self {
+      Data::DNull => 0,
+      Data::DBoolean(_) => 1,
+      Data::DInt(_) | Data::DFloat(_) => 2,
+      Data::DString(_) => 3,
+      Data::DArray(_) => 4,
+      Data::DObject(_) => 5,
+      Data::DBytes(_) => 6,
+    }
+  }
 }
 
 /// The default for ```ndata.Data``` is ```DNull```.
@@ -216,3 +605,191 @@ impl Default for Data {
   }
 }
 
+/// A value removed from an object or array, together with the reference it held. If the value 
+/// is an object, array or byte buffer, that instance stays on the heap until the 
+/// ```OwnedData``` is dropped, even across garbage collection. Dereferences to ```Data```.
+#[derive(Debug)]
+pub struct OwnedData {
+  data: Data,
+}
+
+impl OwnedData {
+  /// Take over one reference count of the instance the given value points to, if any.
+  pub(crate) fn new(data:Data) -> OwnedData {
+    OwnedData { data }
+  }
+  
+  /// Returns the value. Like any ```Data``` value, it does not keep the instance alive by 
+  /// itself, so store it or get a handle to it before this ```OwnedData``` is dropped.
+  pub fn data(&self) -> Data {
+    self.data.clone()
+  }
+}
+
+impl core::ops::Deref for OwnedData {
+  type Target = Data;
+  
+  fn deref(&self) -> &Data {
+    &self.data
+  }
+}
+
+impl Clone for OwnedData {
+  fn clone(&self) -> OwnedData {
+    self.data.incr_ref();
+    OwnedData::new(self.data.clone())
+  }
+}
+
+impl Drop for OwnedData {
+  fn drop(&mut self) {
+    self.data.queue_decr();
+  }
+}
+
+
+impl From<i64> for Data {
+  fn from(i:i64) -> Data {
+    Data::DInt(i)
+  }
+}
+
+impl From<f64> for Data {
+  fn from(f:f64) -> Data {
+    Data::DFloat(f)
+  }
+}
+
+impl From<bool> for Data {
+  fn from(b:bool) -> Data {
+    Data::DBoolean(b)
+  }
+}
+
+impl From<&str> for Data {
+  fn from(s:&str) -> Data {
+    Data::DString(s.to_string())
+  }
+}
+
+impl From<String> for Data {
+  fn from(s:String) -> Data {
+    Data::DString(s)
+  }
+}
+
+/// Like any ```Data``` value, the result does not keep the object instance alive. Store it 
+/// with ```set_property()``` or ```push_property()``` before the next garbage collection.
+impl From<DataObject> for Data {
+  fn from(o:DataObject) -> Data {
+    Data::DObject(o.data_ref)
+  }
+}
+
+/// Like any ```Data``` value, the result does not keep the array instance alive. Store it 
+/// with ```set_property()``` or ```push_property()``` before the next garbage collection.
+impl From<DataArray> for Data {
+  fn from(a:DataArray) -> Data {
+    Data::DArray(a.data_ref)
+  }
+}
+
+/// Like any ```Data``` value, the result does not keep the byte buffer instance alive. Store it 
+/// with ```set_property()``` or ```push_property()``` before the next garbage collection.
+impl From<DataBytes> for Data {
+  fn from(b:DataBytes) -> Data {
+    Data::DBytes(b.data_ref)
+  }
+}
+
+impl From<()> for Data {
+  fn from(_:()) -> Data {
+    Data::DNull
+  }
+}
+
+impl TryFrom<Data> for i64 {
+  type Error = NDataError;
+  fn try_from(d:Data) -> Result<i64, NDataError> {
+    d.try_int()
+  }
+}
+
+impl TryFrom<Data> for f64 {
+  type Error = NDataError;
+  fn try_from(d:Data) -> Result<f64, NDataError> {
+    d.try_float()
+  }
+}
+
+impl TryFrom<Data> for bool {
+  type Error = NDataError;
+  fn try_from(d:Data) -> Result<bool, NDataError> {
+    d.try_boolean()
+  }
+}
+
+impl TryFrom<Data> for String {
+  type Error = NDataError;
+  fn try_from(d:Data) -> Result<String, NDataError> {
+    if let Data::DString(s) = d { Ok(s) } else { Err(d.wrong_type("string")) }
+  }
+}
+
+/// Serializes an object as ```DataObject::to_json()``` would, without building the whole 
+/// ```serde_json::Value``` first. Keys are written in sorted order, as in ```to_json()```.
+#[cfg(feature="serde_support")]
+pub(crate) struct JsonObject<'a>(pub(crate) &'a DataObject);
+
+#[cfg(feature="serde_support")]
+impl serde::Serialize for JsonObject<'_> {
+  fn serialize<S: serde::Serializer>(&self, s:S) -> Result<S::Ok, S::Error> {
+    use serde::ser::SerializeMap;
+    let mut entries = self.0.objects();
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+    let mut m = s.serialize_map(Some(entries.len()))?;
+    for (k, v) in entries {
+      m.serialize_entry(&k, &JsonData(v))?;
+    }
+    m.end()
+  }
+}
+
+/// Serializes an array as ```DataArray::to_json()``` would, without building the whole 
+/// ```serde_json::Value``` first.
+#[cfg(feature="serde_support")]
+pub(crate) struct JsonArray<'a>(pub(crate) &'a DataArray);
+
+#[cfg(feature="serde_support")]
+impl serde::Serialize for JsonArray<'_> {
+  fn serialize<S: serde::Serializer>(&self, s:S) -> Result<S::Ok, S::Error> {
+    use serde::ser::SerializeSeq;
+    let values = self.0.objects();
+    let mut q = s.serialize_seq(Some(values.len()))?;
+    for v in values {
+      q.serialize_element(&JsonData(v))?;
+    }
+    q.end()
+  }
+}
+
+/// Serializes a value contained in an object or array
+#[cfg(feature="serde_support")]
+struct JsonData(Data);
+
+#[cfg(feature="serde_support")]
+impl serde::Serialize for JsonData {
+  fn serialize<S: serde::Serializer>(&self, s:S) -> Result<S::Ok, S::Error> {
+    if let Some(ts) = timestamp_string(&self.0) { return s.serialize_str(&ts); }
+    match &self.0 {
+      Data::DInt(i) => s.serialize_i64(*i),
+      Data::DFloat(f) if f.is_finite() => s.serialize_f64(*f),
+      Data::DBoolean(b) => s.serialize_bool(*b),
+      Data::DString(x) => s.serialize_str(x),
+      Data::DObject(_) => JsonObject(&self.0.object()).serialize(s),
+      Data::DArray(_) => JsonArray(&self.0.array()).serialize(s),
+      Data::DBytes(_) => self.0.bytes().to_json().serialize(s),
+      _ => s.serialize_unit(),
+    }
+  }
+}
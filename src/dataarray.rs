@@ -5,6 +5,7 @@ use crate::data::*;
 use crate::dataobject::*;
 use crate::databytes::*;
 use crate::sharedmutex::*;
+use crate::ndataerror::*;
 
 #[cfg(feature="serde_support")]
 use serde_json::Value;
@@ -13,8 +14,232 @@ use serde_json::json;
 #[cfg(not(feature="serde_support"))]
 use crate::json_util::*;
 
+/// The element type of a packed array created with ```DataArray::new_typed()```
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ElemType {
+  /// Values are stored as ```i64```
+  Int,
+  /// Values are stored as ```f64```
+  Float,
+}
+
+/// A value stored in one of the vectors of ```ArrayValues```
+trait Elem {
+  /// Returns the value as ```Data```.
+  fn into_data(self) -> Data;
+  /// Call ```f``` with the value as ```Data```, without cloning ```Data``` values.
+  fn with_data<R>(&self, f:impl FnOnce(&Data) -> R) -> R;
+}
+
+impl Elem for Data {
+  fn into_data(self) -> Data { self }
+  fn with_data<R>(&self, f:impl FnOnce(&Data) -> R) -> R { f(self) }
+}
+
+impl Elem for i64 {
+  fn into_data(self) -> Data { Data::DInt(self) }
+  fn with_data<R>(&self, f:impl FnOnce(&Data) -> R) -> R { f(&Data::DInt(*self)) }
+}
+
+impl Elem for f64 {
+  fn into_data(self) -> Data { Data::DFloat(self) }
+  fn with_data<R>(&self, f:impl FnOnce(&Data) -> R) -> R { f(&Data::DFloat(*self)) }
+}
+
+/// Evaluate ```$body``` with ```$v``` bound to the vector of whichever representation the 
+/// ```ArrayValues``` uses
+macro_rules! each_vec {
+  ($values:expr, $v:ident => $body:expr) => {
+    match $values {
+      ArrayValues::Data($v) => $body,
+      ArrayValues::Int($v) => $body,
+      ArrayValues::Float($v) => $body,
+    }
+  };
+}
+
+/// The values of an array on the heap. Arrays created with ```DataArray::new_typed()``` keep 
+/// their values packed as a single primitive type until a value of another type is added, or 
+/// until they are changed with a function that takes or returns arbitrary ```Data``` values 
+/// (```splice()```, ```retain()```, ```dedup_by()``` and ```sort_by()```).
+#[derive(Debug)]
+pub enum ArrayValues {
+  /// Values of any type
+  Data(Vec<Data>),
+  /// Values that are all ```DInt```, stored packed
+  Int(Vec<i64>),
+  /// Values that are all ```DFloat```, stored packed
+  Float(Vec<f64>),
+}
+
+impl ArrayValues {
+  /// Returns the values as ```Data```, converting packed values to ```Data``` values first.
+  pub fn values(&mut self) -> &mut Vec<Data> {
+    let unpacked = match self {
+      ArrayValues::Data(_) => None,
+      ArrayValues::Int(v) => Some(v.iter().map(|i| Data::DInt(*i)).collect()),
+      ArrayValues::Float(v) => Some(v.iter().map(|f| Data::DFloat(*f)).collect()),
+    };
+    if let Some(v) = unpacked { *self = ArrayValues::Data(v); }
+    match self {
+      ArrayValues::Data(v) => v,
+      _ => unreachable!(),
+    }
+  }
+  
+  /// Returns the values that may refer to objects, arrays or byte buffers. Packed values never
+  /// do, so this is empty for a packed array.
+  pub(crate) fn instances(&self) -> &[Data] {
+    if let ArrayValues::Data(v) = self { v } else { &[] }
+  }
+  
+  /// Returns the type of the packed values, or ```None``` if the values are not packed.
+  pub fn elem_type(&self) -> Option<ElemType> {
+    match self {
+      ArrayValues::Data(_) => None,
+      ArrayValues::Int(_) => Some(ElemType::Int),
+      ArrayValues::Float(_) => Some(ElemType::Float),
+    }
+  }
+  
+  /// Returns the number of values.
+  pub fn len(&self) -> usize {
+    each_vec!(self, v => v.len())
+  }
+  
+  /// Returns ```true``` if there are no values.
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+  
+  /// Returns the indexed value, or ```None``` if the index is out of bounds.
+  pub fn get(&self, i:usize) -> Option<Data> {
+    each_vec!(self, v => v.get(i).map(|x| x.with_data(Data::clone)))
+  }
+  
+  /// Returns the index of the first value for which ```f``` returns ```true```.
+  pub fn position<F: FnMut(&Data) -> bool>(&self, mut f:F) -> Option<usize> {
+    each_vec!(self, v => v.iter().position(|x| x.with_data(&mut f)))
+  }
+  
+  /// Binary search the values as for ```slice::binary_search_by()```.
+  pub fn binary_search_by<F: FnMut(&Data) -> core::cmp::Ordering>(&self, mut f:F) -> Result<usize, usize> {
+    each_vec!(self, v => v.binary_search_by(|x| x.with_data(&mut f)))
+  }
+  
+  /// Append a value, keeping the values packed if it has the packed type.
+  pub fn push(&mut self, data:Data) {
+    let len = self.len();
+    self.insert(len, data);
+  }
+  
+  /// Insert a value at the given index, keeping the values packed if it has the packed type. 
+  /// Panics if the index is past the end.
+  pub fn insert(&mut self, i:usize, data:Data) {
+    match self {
+      ArrayValues::Int(v) if data.is_int() => v.insert(i, data.int()),
+      ArrayValues::Float(v) if data.is_float() => v.insert(i, data.float()),
+      x => x.values().insert(i, data),
+    }
+  }
+  
+  /// Replace the indexed value and return the old one, keeping the values packed if the new 
+  /// value has the packed type. Panics if the index is out of bounds.
+  pub fn set(&mut self, i:usize, data:Data) -> Data {
+    match self {
+      ArrayValues::Int(v) if data.is_int() => core::mem::replace(&mut v[i], data.int()).into_data(),
+      ArrayValues::Float(v) if data.is_float() => core::mem::replace(&mut v[i], data.float()).into_data(),
+      x => core::mem::replace(&mut x.values()[i], data),
+    }
+  }
+  
+  /// Remove and return the indexed value. Panics if the index is out of bounds.
+  pub fn remove(&mut self, i:usize) -> Data {
+    each_vec!(self, v => v.remove(i).into_data())
+  }
+  
+  /// Remove the indexed value, replacing it with the last value, and return it. Panics if the 
+  /// index is out of bounds.
+  pub fn swap_remove(&mut self, i:usize) -> Data {
+    each_vec!(self, v => v.swap_remove(i).into_data())
+  }
+  
+  /// Remove and return the last value, or ```None``` if there are no values.
+  pub fn pop(&mut self) -> Option<Data> {
+    each_vec!(self, v => v.pop().map(Elem::into_data))
+  }
+  
+  /// Remove and return the values in the given range. Panics if the range is out of bounds.
+  pub fn drain(&mut self, range:core::ops::Range<usize>) -> Vec<Data> {
+    each_vec!(self, v => v.drain(range).map(Elem::into_data).collect())
+  }
+  
+  /// Swap the values at the two indexes. Panics if either index is out of bounds.
+  pub fn swap(&mut self, i:usize, j:usize) {
+    each_vec!(self, v => v.swap(i, j))
+  }
+  
+  /// Reverse the order of the values.
+  pub fn reverse(&mut self) {
+    each_vec!(self, v => v.reverse())
+  }
+  
+  /// Returns a copy of the values in the given range, packed in the same way. Panics if the 
+  /// range is out of bounds.
+  pub fn slice(&self, range:core::ops::Range<usize>) -> ArrayValues {
+    match self {
+      ArrayValues::Data(v) => ArrayValues::Data(v[range].to_vec()),
+      ArrayValues::Int(v) => ArrayValues::Int(v[range].to_vec()),
+      ArrayValues::Float(v) => ArrayValues::Float(v[range].to_vec()),
+    }
+  }
+  
+  /// Returns the number of bytes allocated to hold the values, not counting memory they refer to.
+  pub(crate) fn storage_size(&self) -> usize {
+    match self {
+      ArrayValues::Data(v) => v.capacity() * core::mem::size_of::<Data>(),
+      ArrayValues::Int(v) => v.capacity() * core::mem::size_of::<i64>(),
+      ArrayValues::Float(v) => v.capacity() * core::mem::size_of::<f64>(),
+    }
+  }
+  
+  /// Returns a copy of the values as ```Data```.
+  pub fn to_vec(&self) -> Vec<Data> {
+    each_vec!(self, v => v.iter().map(|x| x.with_data(Data::clone)).collect())
+  }
+}
+
+impl Default for ArrayValues {
+  fn default() -> ArrayValues {
+    ArrayValues::Data(Vec::new())
+  }
+}
+
+impl From<Vec<Data>> for ArrayValues {
+  fn from(v:Vec<Data>) -> ArrayValues {
+    ArrayValues::Data(v)
+  }
+}
+
+/// A read-only view of the values of a packed array, returned by ```DataArray::as_i64_slice()```
+/// and ```DataArray::as_f64_slice()```. The array heap stays locked until this is dropped, so 
+/// no array may be accessed by this thread in the meantime.
+pub struct TypedSlice<T: 'static> {
+  _guard: SharedMutexGuard<'static, Heap<ArrayValues>>,
+  slice: *const [T],
+}
+
+impl<T> core::ops::Deref for TypedSlice<T> {
+  type Target = [T];
+  
+  fn deref(&self) -> &[T] {
+    // The slice belongs to an array on the heap, which cannot change while the heap is locked
+    unsafe { &*self.slice }
+  }
+}
+
 /// Storage for runtime array values
-static mut AH:SharedMutex<Heap<Vec<Data>>> = SharedMutex::new();
+static mut AH:SharedMutex<Heap<ArrayValues>> = SharedMutex::new();
 
 /// Storage for runtime reference count reductions
 static mut AD:SharedMutex<Vec<usize>> = SharedMutex::new();
@@ -22,23 +247,39 @@ static mut AD:SharedMutex<Vec<usize>> = SharedMutex::new();
 /// **DO NOT USE**
 ///
 /// This function should only be used externally by DataObject
-pub fn aheap() -> &'static mut SharedMutex<Heap<Vec<Data>>> {
+pub fn aheap() -> &'static mut SharedMutex<Heap<ArrayValues>> {
   #[allow(static_mut_refs)]
   unsafe { &mut AH }
 }
 
-fn adrop() -> &'static mut SharedMutex<Vec<usize>> {
+pub(crate) fn adrop() -> &'static mut SharedMutex<Vec<usize>> {
   #[allow(static_mut_refs)]
   unsafe { &mut AD }
 }
 
 /// Represents an array of type ```ndata.Data```. 
-#[derive(Debug, Default)]
 pub struct DataArray {
   /// The pointer to the array in the array heap.
   pub data_ref: usize,
 }
 
+/// Shows the contents of the array as well as its data_ref. Object keys are written in sorted
+/// order, and contents that cannot be read without waiting for a lock are omitted.
+impl core::fmt::Debug for DataArray {
+  fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+    write!(f, "DataArray {{ data_ref: {}, value: ", self.data_ref)?;
+    Data::DArray(self.data_ref).debug_contents(f, &mut Vec::new())?;
+    write!(f, " }}")
+  }
+}
+
+/// The default for ```DataArray``` is a new (empty) array, as returned by ```DataArray::new()```.
+impl Default for DataArray {
+  fn default() -> DataArray {
+    DataArray::new()
+  }
+}
+
 impl Clone for DataArray{
   /// Returns another DataArray pointing to the same value.
   fn clone(&self) -> Self {
@@ -53,8 +294,14 @@ impl Clone for DataArray{
 impl DataArray {
   /// Initialize global storage of arrays. Call only once at startup.
   pub fn init() -> ((u64, u64),(u64, u64)){
+    DataArray::init_with_heap(Heap::new())
+  }
+  
+  /// Initialize global storage of arrays using the given (empty) heap, such as one created 
+  /// with ```Heap::in_region()```. Call only once at startup, instead of ```init()```.
+  pub fn init_with_heap(heap:Heap<ArrayValues>) -> ((u64, u64),(u64, u64)){
     unsafe {
-      AH.set(Heap::new());
+      AH.set(heap);
       AD.set(Vec::new());
     }
     DataArray::share()
@@ -76,14 +323,39 @@ impl DataArray {
     }
   }
   
+  /// Set how this process waits when the array storage is locked by another thread.
+  pub fn set_contention_policy(policy:ContentionPolicy) {
+    aheap().set_contention_policy(policy);
+    adrop().set_contention_policy(policy);
+  }
+  
   /// Create a new (empty) array.
   pub fn new() -> DataArray {
-    let data_ref = &mut aheap().lock().push(Vec::<Data>::new());
+    let data_ref = &mut aheap().lock().push(ArrayValues::default());
     return DataArray {
       data_ref: *data_ref,
     };
   }
   
+  /// Create a new (empty) array with space for at least ```capacity``` values, so that it does
+  /// not reallocate until it grows past that size.
+  pub fn with_capacity(capacity:usize) -> DataArray {
+    let data_ref = &mut aheap().lock().push(ArrayValues::Data(Vec::with_capacity(capacity)));
+    DataArray {
+      data_ref: *data_ref,
+    }
+  }
+  
+  /// Reserve space for at least ```additional``` more values in this array.
+  pub fn reserve(&mut self, additional:usize) {
+    let aheap = &mut aheap().lock();
+    match aheap.get(self.data_ref) {
+      ArrayValues::Data(v) => v.reserve(additional),
+      ArrayValues::Int(v) => v.reserve(additional),
+      ArrayValues::Float(v) => v.reserve(additional),
+    }
+  }
+  
   /// Get a reference to the array from the heap
   pub fn get(data_ref: usize) -> DataArray {
     let o = DataArray{
@@ -121,6 +393,25 @@ impl DataArray {
     self.to_json().to_string()
   }  
   
+  /// Create an indented JSON string from a DataArray, with ```indent``` spaces per level of 
+  /// nesting.
+  pub fn to_string_pretty(&self, indent:usize) -> String {
+    #[cfg(not(feature="serde_support"))]
+    return array_to_string_pretty(self.clone(), indent, false);
+    #[cfg(feature="serde_support")]
+    reindent(&serde_json::to_string_pretty(&self.to_json()).unwrap(), indent)
+  }
+  
+  /// Write this array as JSON to the given writer, without first building the whole JSON
+  /// string in memory.
+  #[cfg(not(feature="no_std_support"))]
+  pub fn write_json<W: std::io::Write>(&self, w:&mut W) -> std::io::Result<()> {
+    #[cfg(feature="serde_support")]
+    return serde_json::to_writer(w, &JsonArray(self)).map_err(|e| e.into());
+    #[cfg(not(feature="serde_support"))]
+    return write_array_io(w, self);
+  }
+  
   /// Create a new array from the ```serde_json::Value```.
   #[cfg(feature="serde_support")]
   pub fn from_json(value:Value) -> DataArray {
@@ -131,7 +422,11 @@ impl DataArray {
       else if val.is_boolean() { o.push_boolean(val.as_bool().unwrap()); }
       else if val.is_i64() { o.push_int(val.as_i64().unwrap()); }
       else if val.is_f64() { o.push_float(val.as_f64().unwrap()); }
-      else if val.is_object() { o.push_object(DataObject::from_json(val.to_owned())); }
+      else if val.is_object() {
+        #[cfg(feature="bytes_json")]
+        if let Some(b) = DataBytes::from_tagged_json(val) { o.push_bytes(b); continue; }
+        o.push_object(DataObject::from_json(val.to_owned()));
+      }
       else if val.is_array() { o.push_array(DataArray::from_json(val.to_owned())); }      
       else { println!("Unknown type {}", val) };
     }
@@ -140,22 +435,31 @@ impl DataArray {
   }
   
   /// Return the array as a ```serde_json::Value```.
+  ///
+  /// Floats that are ```NaN``` or infinite become ```null```, since JSON cannot represent them.
+  /// Timestamps become strings if ```set_timestamps_as_rfc3339()``` is enabled.
   #[cfg(feature="serde_support")]
   pub fn to_json(&self) -> Value {
+    json!(self.to_json_vec())
+  }
+  
+  /// Returns each element of this array as a separate ```serde_json::Value```, converted as by
+  /// ```to_json()```.
+  #[cfg(feature="serde_support")]
+  pub fn to_json_vec(&self) -> Vec<Value> {
     let mut val = Vec::<Value>::new();
-    let mut id = 0;
     for old in self.objects() {
-      if old.is_int() { val.push(json!(self.get_int(id))); }
-      else if old.is_float() { val.push(json!(self.get_float(id))); }
-      else if old.is_boolean() { val.push(json!(self.get_boolean(id))); }
-      else if old.is_string() { val.push(json!(self.get_string(id))); }
-      else if old.is_object() { val.push(self.get_object(id).to_json()); }
-      else if old.is_array() { val.push(self.get_array(id).to_json()); }
-      else if old.is_bytes() { val.push(json!(self.get_bytes(id).to_hex_string())); }
+      if old.is_int() { val.push(json!(old.int())); }
+      else if old.is_float() { val.push(json!(old.float())); }
+      else if old.is_boolean() { val.push(json!(old.boolean())); }
+      else if old.is_string() { val.push(json!(old.string())); }
+      else if let Some(ts) = timestamp_string(&old) { val.push(json!(ts)); }
+      else if old.is_object() { val.push(old.object().to_json()); }
+      else if old.is_array() { val.push(old.array().to_json()); }
+      else if old.is_bytes() { val.push(old.bytes().to_json()); }
       else { val.push(json!(null)); }
-      id = id + 1;
     }
-    json!(val)
+    val
   }
   
   /// Returns a new ```DataArray``` that points to the same underlying array instance.
@@ -197,25 +501,101 @@ impl DataArray {
     o
   }
 
+  /// Returns a new ```DataArray``` for each overlapping window of ```size``` consecutive values
+  /// in this array, like ```slice::windows()```. Returns an empty list if ```size``` is greater
+  /// than the length of the array, and panics if ```size``` is zero.
+  pub fn windows(&self, size:usize) -> Vec<DataArray> {
+    if size == 0 { panic!("Window size must be greater than zero"); }
+    let values = self.objects();
+    let mut windows = Vec::<DataArray>::new();
+    for window in values.windows(size) {
+      let mut a = DataArray::new();
+      for v in window {
+        a.push_property(v.clone());
+      }
+      windows.push(a);
+    }
+    windows
+  }
+
+  /// Returns the total number of nodes in this array, counted as for ```DataObject::deep_len()```.
+  pub fn deep_len(&self) -> usize {
+    deep_len_of(Data::DArray(self.data_ref), &mut Vec::new(), &mut Vec::new())
+  }
+
+  /// Returns a hash of the content of this array, which is the same for any two arrays that
+  /// are structurally equal. See ```Data::content_hash()```.
+  pub fn content_hash(&self) -> u64 {
+    Data::DArray(self.data_ref).content_hash()
+  }
+
   /// Returns the length of the array.
   pub fn len(&self) -> usize {
     let heap = &mut aheap().lock();
-    let vec = heap.get(self.data_ref);
-    vec.len()
+    heap.get(self.data_ref).len()
+  }
+  
+  /// Returns ```true``` if the array has no values or is no longer on the heap.
+  pub fn is_empty(&self) -> bool {
+    let heap = &mut aheap().lock();
+    heap.try_get(self.data_ref).is_none_or(|vec| vec.is_empty())
+  }
+  
+  /// Returns the elements of this array concatenated into a single ```String```, with ```sep``` 
+  /// between each. Elements are stringified with ```Data::as_string()```, so strings are 
+  /// included as-is (unquoted), objects and arrays as JSON, byte buffers as hex and null as 
+  /// ```"null"```.
+  pub fn join_strings(&self, sep:&str) -> String {
+    let list = self.objects();
+    let mut s = String::new();
+    for (i, d) in list.into_iter().enumerate() {
+      if i > 0 { s.push_str(sep); }
+      s.push_str(&Data::as_string(d));
+    }
+    s
   }
   
   /// Returns the index of a Data in the array
   pub fn index_of(&self, b: Data) -> i64 {
     let heap = &mut aheap().lock();
-    let vec = heap.get(self.data_ref);
-    let mut i = 0;
-    let n = vec.len();
-    while i<n {
-      let d = vec.get(i).unwrap();
-      if Data::equals(d.clone(),b.clone()) { return i as i64; }
-      i = i + 1;
+    match heap.get(self.data_ref).position(|d| Data::equals(d.clone(), b.clone())) {
+      Some(i) => i as i64,
+      None => -1,
+    }
+  }
+  
+  /// Returns ```true``` if the array contains a value equal to ```d```, compared with 
+  /// ```Data::equals()```.
+  pub fn contains(&self, d:&Data) -> bool {
+    let heap = &mut aheap().lock();
+    heap.get(self.data_ref).position(|x| Data::equals(x.clone(), d.clone())).is_some()
+  }
+  
+  /// Binary search this array, which must already be sorted in the order used by ```f```, as
+  /// for ```slice::binary_search_by()```. ```f``` returns how each element compares to the 
+  /// target. Returns ```Ok``` with the index of a matching element, or ```Err``` with the index
+  /// where the target could be inserted to keep the array sorted.
+  ///
+  /// The array is locked while ```f``` is called, so ```f``` must not access any array.
+  pub fn binary_search_by<F: FnMut(&Data) -> core::cmp::Ordering>(&self, f:F) -> Result<usize, usize> {
+    let heap = &mut aheap().lock();
+    heap.get(self.data_ref).binary_search_by(f)
+  }
+  
+  /// Returns ```true``` if the two arrays contain structurally equal values, regardless of 
+  /// order. Each value in one array must be matched by a distinct value in the other, so 
+  /// duplicates must occur the same number of times in both.
+  pub fn equals_unordered(&self, other:&DataArray) -> bool {
+    let mine = self.objects();
+    let mut theirs = other.objects();
+    if mine.len() != theirs.len() { return false; }
+    for d in mine {
+      match theirs.iter().position(|x| Data::deep_equals(&d, x)) {
+        Some(i) => { theirs.swap_remove(i); },
+        None => return false,
+      }
     }
-    -1
+    true
   }
   
   /// Push data if not already in array
@@ -223,13 +603,7 @@ impl DataArray {
     {
       let heap = &mut aheap().lock();
       let vec = heap.get(self.data_ref);
-      let mut i = 0;
-      let n = vec.len();
-      while i<n {
-        let d = vec.get(i).unwrap();
-        if Data::equals(d.clone(),b.clone()) { return false; }
-        i = i + 1;
-      }
+      if vec.position(|d| Data::equals(d.clone(), b.clone())).is_some() { return false; }
       vec.push(b.clone());
     }
     if let Data::DObject(i) = &b {
@@ -244,6 +618,43 @@ impl DataArray {
     true
   }
   
+  /// Returns a new array containing the distinct values of this array followed by the distinct
+  /// values of ```other``` that are not in this array. Values are compared with 
+  /// ```Data::equals()```, so objects, arrays, and byte buffers match only the same instance.
+  ///
+  /// Values are compared pairwise, so this takes O(n*m) time.
+  pub fn union(&self, other:&DataArray) -> DataArray {
+    let mut list = self.objects();
+    list.append(&mut other.objects());
+    DataArray::distinct(list, |_d| true)
+  }
+  
+  /// Returns a new array containing the distinct values of this array that are also in
+  /// ```other```, compared as for ```union()```. Takes O(n*m) time.
+  pub fn intersection(&self, other:&DataArray) -> DataArray {
+    let theirs = other.objects();
+    DataArray::distinct(self.objects(), |d| theirs.iter().any(|x| Data::equals(d.clone(), x.clone())))
+  }
+  
+  /// Returns a new array containing the distinct values of this array that are not in 
+  /// ```other```, compared as for ```union()```. Takes O(n*m) time.
+  pub fn difference(&self, other:&DataArray) -> DataArray {
+    let theirs = other.objects();
+    DataArray::distinct(self.objects(), |d| !theirs.iter().any(|x| Data::equals(d.clone(), x.clone())))
+  }
+  
+  fn distinct(list:Vec<Data>, keep:impl Fn(&Data) -> bool) -> DataArray {
+    let mut a = DataArray::new();
+    let mut seen = Vec::<Data>::new();
+    for d in list {
+      if keep(&d) && !seen.iter().any(|x| Data::equals(d.clone(), x.clone())) {
+        seen.push(d.clone());
+        a.push_property(d);
+      }
+    }
+    a
+  }
+  
   /// Returns the index of a Data in the array
   pub fn remove_data(&self, b: Data) -> bool {
     let heap = &mut aheap().lock();
@@ -278,10 +689,20 @@ impl DataArray {
   
   /// Returns the indexed value from the array
   pub fn get_property(&self, id:usize) -> Data {
+    match self.try_get_property(id) {
+      Ok(data) => data,
+      Err(e) => panic!("{}", e),
+    }
+  }
+  
+  /// Returns the indexed value from the array, or an error if the index is out of bounds
+  pub fn try_get_property(&self, id:usize) -> Result<Data, NDataError> {
     let heap = &mut aheap().lock();
     let vec = heap.get(self.data_ref);
-    let data = vec.get_mut(id).unwrap();
-    data.clone()
+    match vec.get(id) {
+      Some(data) => Ok(data),
+      None => Err(NDataError::IndexOutOfBounds { index: id, len: vec.len() }),
+    }
   }
   
   /// Returns the indexed value from the array as a String
@@ -324,6 +745,13 @@ impl DataArray {
     d.float()
   }
 
+  /// Returns the indexed value from the array as an f64, or ```None``` if the index is out of
+  /// bounds or the value cannot be read as a number. String values are parsed as for 
+  /// ```DataObject::get_float_lenient()```.
+  pub fn get_float_lenient(&self, id:usize) -> Option<f64> {
+    self.try_get_property(id).ok()?.to_float_lenient()
+  }
+
   /// Returns the indexed value from the array as a DataArray
   pub fn get_array(&self, id:usize) -> DataArray {
     self.get_property(id).array()
@@ -339,6 +767,44 @@ impl DataArray {
     self.get_property(id).bytes()
   }
   
+  /// Returns the indexed value from the array as a String, or an error if it is not a string
+  pub fn try_get_string(&self, id:usize) -> Result<String, NDataError> {
+    self.try_get_property(id)?.try_string()
+  }
+
+  /// Returns the indexed value from the array as a bool, or an error if it is not a boolean
+  pub fn try_get_boolean(&self, id:usize) -> Result<bool, NDataError> {
+    self.try_get_property(id)?.try_boolean()
+  }
+
+  /// Returns the indexed value from the array as an i64, or an error if it is not an int
+  pub fn try_get_int(&self, id:usize) -> Result<i64, NDataError> {
+    self.try_get_property(id)?.try_int()
+  }
+
+  /// Returns the indexed value from the array as an f64, or an error if it is not a float.
+  /// As with ```get_float```, an int value is converted to f64.
+  pub fn try_get_float(&self, id:usize) -> Result<f64, NDataError> {
+    let d = self.try_get_property(id)?;
+    if d.is_int() { return Ok(d.int() as f64); }
+    d.try_float()
+  }
+
+  /// Returns the indexed value from the array as a DataArray, or an error if it is not an array
+  pub fn try_get_array(&self, id:usize) -> Result<DataArray, NDataError> {
+    self.try_get_property(id)?.try_array()
+  }
+
+  /// Returns the indexed value from the array as a DataObject, or an error if it is not an object
+  pub fn try_get_object(&self, id:usize) -> Result<DataObject, NDataError> {
+    self.try_get_property(id)?.try_object()
+  }
+
+  /// Returns the indexed value from the array as a DataBytes, or an error if it is not a byte buffer
+  pub fn try_get_bytes(&self, id:usize) -> Result<DataBytes, NDataError> {
+    self.try_get_property(id)?.try_bytes()
+  }
+  
   /// Append all values from another array
   pub fn join(&mut self, a:DataArray) {
     for val in a.objects() {
@@ -363,6 +829,35 @@ impl DataArray {
     vec.push(data);
   }
 
+  /// Insert the given value at the start of the array
+  pub fn push_front(&mut self, data:Data) {
+    data.incr_ref();
+    let aheap = &mut aheap().lock();
+    let vec = aheap.get(self.data_ref);
+    vec.insert(0, data);
+  }
+
+  /// Remove and return the first value in the array, or ```None``` if the array is empty.
+  ///
+  /// Unlike ```pop_property()```, the array's reference is handed to the caller, so a removed 
+  /// object, array, or byte buffer stays on the heap until the returned value is dropped.
+  pub fn pop_front(&mut self) -> Option<OwnedData> {
+    let aheap = &mut aheap().lock();
+    let vec = aheap.get(self.data_ref);
+    if vec.is_empty() { return None; }
+    Some(OwnedData::new(vec.remove(0)))
+  }
+
+  /// Remove and return the last value in the array, or ```None``` if the array is empty.
+  ///
+  /// Unlike ```pop_property()```, the array's reference is handed to the caller, so a removed 
+  /// object, array, or byte buffer stays on the heap until the returned value is dropped.
+  pub fn pop_back(&mut self) -> Option<OwnedData> {
+    let aheap = &mut aheap().lock();
+    let vec = aheap.get(self.data_ref);
+    vec.pop().map(OwnedData::new)
+  }
+
   /// Append the given ```String``` to the end of the array
   #[deprecated(since="0.3.0", note="please use `push_string` instead")]
   pub fn push_str(&mut self, val:&str) {
@@ -440,8 +935,7 @@ impl DataArray {
   
     let aheap = &mut aheap().lock();
     let vec = aheap.get(self.data_ref);
-    let old = vec[id].clone();
-    vec[id] = data;
+    let old = vec.set(id, data);
     
     if let Data::DObject(i) = &old {
       let _x = DataObject {
@@ -540,6 +1034,49 @@ impl DataArray {
     }
   }
 
+  /// Remove every value for which the given function returns ```false```, keeping the order of 
+  /// the remaining values. Returns the number of values removed. The function is called while 
+  /// the array heap is locked, so it must not access any ```DataArray```.
+  pub fn retain<F: FnMut(&Data) -> bool>(&mut self, mut f: F) -> usize {
+    let aheap = &mut aheap().lock();
+    let vec = aheap.get(self.data_ref).values();
+    let len = vec.len();
+    let mut removed = Vec::new();
+    vec.retain(|v| {
+      let keep = f(v);
+      if !keep { removed.push(v.clone()); }
+      keep
+    });
+    for old in &removed { old.queue_decr(); }
+    len - vec.len()
+  }
+  
+  /// Remove consecutive values that are equal according to ```Data::equals()```, keeping the 
+  /// first of each run, and return the number of values removed. On a sorted array this removes
+  /// all duplicates.
+  pub fn dedup(&mut self) -> usize {
+    self.dedup_by(|a, b| Data::equals(a.clone(), b.clone()))
+  }
+  
+  /// Remove each value for which ```f(previous, value)``` returns ```true```, where 
+  /// ```previous``` is the last value kept, and return the number of values removed.
+  ///
+  /// The array is locked while ```f``` is called, so ```f``` must not access any array.
+  pub fn dedup_by<F: FnMut(&Data, &Data) -> bool>(&mut self, mut f: F) -> usize {
+    let aheap = &mut aheap().lock();
+    let vec = aheap.get(self.data_ref).values();
+    let mut removed = Vec::new();
+    vec.dedup_by(|v, prev| {
+      let same = f(prev, v);
+      if same { removed.push(v.clone()); }
+      same
+    });
+    for old in &removed {
+      old.queue_decr();
+    }
+    removed.len()
+  }
+  
   /// Pop the indexed value from the array
   pub fn pop_property(&mut self, id:usize) -> Data {
     let aheap = &mut aheap().lock();
@@ -563,19 +1100,144 @@ impl DataArray {
     old
   }
 
+  /// Remove and return the indexed value, replacing it with the last value in the array. This 
+  /// does not preserve the order of the array, but takes O(1) time. Panics if the index is out
+  /// of bounds.
+  ///
+  /// Unlike ```pop_property()```, the array's reference is handed to the caller, so a removed 
+  /// object, array, or byte buffer stays on the heap until the returned value is dropped.
+  pub fn swap_remove(&mut self, id:usize) -> OwnedData {
+    match self.try_swap_remove(id) {
+      Ok(d) => d,
+      Err(e) => panic!("{}", e),
+    }
+  }
+
+  /// Remove and return the indexed value as for ```swap_remove()```, or return an error if the
+  /// index is out of bounds.
+  pub fn try_swap_remove(&mut self, id:usize) -> Result<OwnedData, NDataError> {
+    let aheap = &mut aheap().lock();
+    let vec = aheap.get(self.data_ref);
+    if id >= vec.len() { return Err(NDataError::IndexOutOfBounds { index: id, len: vec.len() }); }
+    Ok(OwnedData::new(vec.swap_remove(id)))
+  }
+
+  /// Remove up to ```delete_count``` values starting at index ```start``` and insert ```items``` 
+  /// in their place, returning the removed values. ```start``` may equal the length of the array
+  /// to append. As with ```drain_range()```, removed objects, arrays and byte buffers stay on the
+  /// heap until the returned values are dropped. Panics if ```start``` is past the end of the 
+  /// array.
+  pub fn splice(&mut self, start:usize, delete_count:usize, items:Vec<Data>) -> Vec<OwnedData> {
+    for d in &items {
+      d.incr_ref();
+    }
+    let removed = {
+      let aheap = &mut aheap().lock();
+      let vec = aheap.get(self.data_ref).values();
+      let len = vec.len();
+      if start > len { Err(len) }
+      else {
+        let end = start + delete_count.min(len - start);
+        Ok(vec.splice(start..end, items.iter().cloned()).map(OwnedData::new).collect::<Vec<OwnedData>>())
+      }
+    };
+    match removed {
+      Ok(removed) => removed,
+      Err(len) => {
+        for d in &items {
+          d.queue_decr();
+        }
+        panic!("{}", NDataError::IndexOutOfBounds { index: start, len });
+      },
+    }
+  }
+  
+  /// Insert the value at the given index, shifting the values after it to the right. An index 
+  /// equal to the length of the array appends the value. Panics if the index is past the end of 
+  /// the array.
+  pub fn insert_at(&mut self, index:usize, data:Data) {
+    data.incr_ref();
+    let len = {
+      let aheap = &mut aheap().lock();
+      let vec = aheap.get(self.data_ref);
+      let len = vec.len();
+      if index <= len { vec.insert(index, data.clone()); }
+      len
+    };
+    if index > len {
+      data.queue_decr();
+      panic!("{}", NDataError::IndexOutOfBounds { index, len });
+    }
+  }
+  
+  /// Swap the values at the two given indexes. Panics if either index is out of bounds.
+  pub fn swap(&mut self, i:usize, j:usize) {
+    let aheap = &mut aheap().lock();
+    let vec = aheap.get(self.data_ref);
+    let len = vec.len();
+    if let Some(index) = [i, j].into_iter().find(|x| *x >= len) {
+      panic!("{}", NDataError::IndexOutOfBounds { index, len });
+    }
+    vec.swap(i, j);
+  }
+  
+  /// Reverse the order of the values in the array.
+  pub fn reverse(&mut self) {
+    let aheap = &mut aheap().lock();
+    let vec = aheap.get(self.data_ref);
+    vec.reverse();
+  }
+  
+  /// Remove every value from the array and return the number removed.
+  pub fn clear(&mut self) -> usize {
+    let aheap = &mut aheap().lock();
+    let vec = aheap.get(self.data_ref);
+    let n = vec.len();
+    for old in vec.drain(0..n) {
+      old.queue_decr();
+    }
+    n
+  }
+  
+  /// Shorten the array to the given length, discarding the remaining values. Has no effect if
+  /// the array is already shorter than ```len```.
+  pub fn truncate(&mut self, len:usize) {
+    let aheap = &mut aheap().lock();
+    let vec = aheap.get(self.data_ref);
+    let n = vec.len();
+    if len >= n { return; }
+    for old in vec.drain(len..n) {
+      old.queue_decr();
+    }
+  }
+
+  /// Remove and return the values in the range ```start..end```. Panics if the range is out of
+  /// bounds.
+  ///
+  /// Unlike ```truncate()```, the references held by the array are handed to the caller, so 
+  /// removed objects, arrays, and byte buffers stay on the heap until the returned values are 
+  /// dropped.
+  pub fn drain_range(&mut self, start:usize, end:usize) -> Vec<OwnedData> {
+    let aheap = &mut aheap().lock();
+    let vec = aheap.get(self.data_ref);
+    let len = vec.len();
+    if start > end || end > len { panic!("Range {}..{} out of bounds for array of length {}", start, end, len); }
+    vec.drain(start..end).into_iter().map(OwnedData::new).collect()
+  }
+
   /// **DO NOT USE**
   ///
   /// Reduces the reference count for this array by one, as well as the reference counts of any
   /// objects, arrays, or byte buffers contained in this array. This function should only be used
   /// externally by ```DataObject::gc()```.
-  pub fn delete(aheap:&mut Heap<Vec<Data>>, data_ref:usize, oheap:&mut Heap<HashMap<String,Data>>) {
+  pub fn delete(aheap:&mut Heap<ArrayValues>, data_ref:usize, oheap:&mut Heap<HashMap<String,Data>>) {
     let mut objects_to_kill = Vec::<usize>::new();
     let mut arrays_to_kill = Vec::<usize>::new();
     
     let n = aheap.count(data_ref);
     if n == 1 {
       let map = aheap.get(data_ref);
-      for v in map {
+      for v in map.instances() {
         if let Data::DObject(i) = v {
           objects_to_kill.push(*i);
         }
@@ -602,40 +1264,363 @@ impl DataArray {
   /// Returns this array as a ```Vec<Data>```. 
   pub fn objects(&self) -> Vec<Data> {
     let heap = &mut aheap().lock();
-    let map = heap.get(self.data_ref);
-    let mut vec = Vec::<Data>::new();
-    for v in map {
-      vec.push(v.clone());
+    heap.get(self.data_ref).to_vec()
+  }
+  
+  /// Returns an iterator over the values in this array. The values are copied when this is 
+  /// called, so later changes to the array are not seen by the iterator. The iterator holds a 
+  /// reference to this array until it is dropped.
+  pub fn iter(&self) -> DataArrayIter {
+    DataArrayIter {
+      _arr: self.clone(),
+      iter: self.objects().into_iter(),
+    }
+  }
+  
+  /// Returns a new array containing the leading values of this array for which the given 
+  /// function returns ```true```.
+  pub fn take_while(&self, mut f: impl FnMut(&Data) -> bool) -> DataArray {
+    let mut a = DataArray::new();
+    for d in self.objects().into_iter().take_while(|d| f(d)) {
+      a.push_property(d);
+    }
+    a
+  }
+  
+  /// Returns a new array containing the values of this array that follow the leading values 
+  /// for which the given function returns ```true```.
+  pub fn skip_while(&self, mut f: impl FnMut(&Data) -> bool) -> DataArray {
+    let mut a = DataArray::new();
+    for d in self.objects().into_iter().skip_while(|d| f(d)) {
+      a.push_property(d);
+    }
+    a
+  }
+  
+  /// Returns a new array containing the values of this array from index ```start``` up to but 
+  /// not including ```end```. Indices past the end of this array are clamped to its length, and
+  /// if ```start``` is not less than ```end``` the new array is empty.
+  pub fn slice(&self, start:usize, end:usize) -> DataArray {
+    let values = {
+      let heap = &mut aheap().lock();
+      let vec = heap.get(self.data_ref);
+      let end = end.min(vec.len());
+      let start = start.min(end);
+      vec.slice(start..end)
+    };
+    for d in values.instances() {
+      d.incr_ref();
+    }
+    let data_ref = aheap().lock().push(values);
+    DataArray { data_ref }
+  }
+  
+  /// Returns the first value in this array, or ```None``` if it is empty.
+  pub fn first(&self) -> Option<Data> {
+    let heap = &mut aheap().lock();
+    heap.get(self.data_ref).get(0)
+  }
+  
+  /// Returns the last value in this array, or ```None``` if it is empty.
+  pub fn last(&self) -> Option<Data> {
+    let heap = &mut aheap().lock();
+    let vec = heap.get(self.data_ref);
+    vec.get(vec.len().checked_sub(1)?)
+  }
+  
+  /// Returns a new object mapping the value of the given key in each object in this array, 
+  /// stringified with ```Data::as_string()```, to that object. Values that are not objects, and
+  /// objects without the key, are skipped. If several objects have the same value, the last 
+  /// one is kept. The objects themselves are not copied.
+  pub fn index_by(&self, key:&str) -> DataObject {
+    let mut index = DataObject::new();
+    for (_i, o) in self.enumerate_objects() {
+      if let Ok(v) = o.try_get_property(key) {
+        index.put_object(&Data::as_string(v), o);
+      }
+    }
+    index
+  }
+  
+  /// Sort this array in place with the given comparator, keeping the order of equal values. 
+  /// Values only move, so no reference counts change.
+  ///
+  /// The comparator is called without holding the array lock, so it may read the objects being
+  /// sorted. The sorted order is stored only if the array was not changed by another thread in 
+  /// the meantime, otherwise the sort is repeated.
+  pub fn sort_by<F: FnMut(&Data, &Data) -> core::cmp::Ordering>(&mut self, mut f: F) {
+    loop {
+      let snapshot = self.objects();
+      let mut sorted = snapshot.clone();
+      sorted.sort_by(&mut f);
+      let heap = &mut aheap().lock();
+      let vec = heap.get(self.data_ref).values();
+      if vec.len() == snapshot.len() && vec.iter().zip(snapshot.iter()).all(|(a, b)| Data::compare(a, b).is_eq() && a.type_name() == b.type_name()) {
+        *vec = sorted;
+        return;
+      }
+    }
+  }
+  
+  /// Sort this array in ascending order using ```Data::compare()```, which orders values of 
+  /// different types by type and places ```NaN``` after all other numbers.
+  pub fn sort(&mut self) {
+    self.sort_by(Data::compare);
+  }
+  
+  /// Sort this array of ints in ascending order. Panics if any value is not a ```DInt```.
+  pub fn sort_ints(&mut self) {
+    let heap = &mut aheap().lock();
+    if let ArrayValues::Int(v) = heap.get(self.data_ref) {
+      v.sort();
+      return;
+    }
+    let vec = heap.get(self.data_ref).values();
+    if let Some((i, d)) = vec.iter().enumerate().find(|(_i, d)| !d.is_int()) {
+      panic!("Cannot sort array as ints, found {} at index {}", d.type_name(), i);
+    }
+    vec.sort_by_key(|d| d.int());
+  }
+  
+  /// Sort this array of numbers in ascending order, comparing ```DInt``` values as ```f64```. 
+  /// Panics if any value is not a number.
+  pub fn sort_floats(&mut self) {
+    let heap = &mut aheap().lock();
+    if let ArrayValues::Float(v) = heap.get(self.data_ref) {
+      v.sort_by(|a, b| a.total_cmp(b));
+      return;
+    }
+    let vec = heap.get(self.data_ref).values();
+    if let Some((i, d)) = vec.iter().enumerate().find(|(_i, d)| !d.is_number()) {
+      panic!("Cannot sort array as floats, found {} at index {}", d.type_name(), i);
+    }
+    let f = |d:&Data| if let Data::DInt(i) = d { *i as f64 } else { d.float() };
+    vec.sort_by(|a, b| f(a).total_cmp(&f(b)));
+  }
+  
+  /// Returns the objects in this array paired with their indices, skipping values that are not
+  /// objects.
+  pub fn enumerate_objects(&self) -> Vec<(usize, DataObject)> {
+    let mut list = Vec::new();
+    for (i, d) in self.objects().into_iter().enumerate() {
+      if let Data::DObject(data_ref) = d {
+        list.push((i, DataObject::get(data_ref)));
+      }
+    }
+    list
+  }
+  
+  /// Returns the type name (as returned by ```Data::type_name()```) shared by every value in 
+  /// this array, or ```None``` if the values are of different types. An empty array returns 
+  /// ```None```, since it has no type to report. The array is read under a single lock.
+  pub fn is_homogeneous(&self) -> Option<&'static str> {
+    let heap = &mut aheap().lock();
+    let vec = heap.get(self.data_ref);
+    if vec.elem_type().is_some() { return Some(vec.get(0)?.type_name()); }
+    let vec = vec.values();
+    let t = vec.first()?.type_name();
+    if vec.iter().all(|d| d.type_name() == t) { Some(t) } else { None }
+  }
+  
+  /// Returns the values in this array as a ```Vec<i64>```, or ```None``` if any value is not 
+  /// a ```DInt```. The array is read under a single lock.
+  pub fn to_int_vec(&self) -> Option<Vec<i64>> {
+    let heap = &mut aheap().lock();
+    match heap.get(self.data_ref) {
+      ArrayValues::Int(v) => Some(v.clone()),
+      ArrayValues::Float(v) => if v.is_empty() { Some(Vec::new()) } else { None },
+      ArrayValues::Data(v) => v.iter().map(|d| if let Data::DInt(i) = d { Some(*i) } else { None }).collect(),
     }
-    vec
+  }
+  
+  /// Returns the values in this array as a ```Vec<f64>```, or ```None``` if any value is not 
+  /// a number. ```DInt``` values are converted to ```f64```. The array is read under a single 
+  /// lock.
+  pub fn to_float_vec(&self) -> Option<Vec<f64>> {
+    let heap = &mut aheap().lock();
+    match heap.get(self.data_ref) {
+      ArrayValues::Int(v) => Some(v.iter().map(|i| *i as f64).collect()),
+      ArrayValues::Float(v) => Some(v.clone()),
+      ArrayValues::Data(v) => v.iter().map(|d| match d {
+        Data::DFloat(f) => Some(*f),
+        Data::DInt(i) => Some(*i as f64),
+        _ => None,
+      }).collect(),
+    }
+  }
+  
+  /// Create a new (empty) array that stores its values packed as ```i64``` or ```f64```, 
+  /// without the overhead of a ```Data``` per value, for as long as every value has that type.
+  ///
+  /// Adding values of the packed type (with ```push_int()```, ```push_float()```, 
+  /// ```push_property()```, ```set_property()```, ```insert_at()``` and so on) keeps the array 
+  /// packed, as do reading, searching, slicing and serializing it, removing values, and 
+  /// reordering it with ```swap()```, ```reverse()```, ```sort_ints()``` or ```sort_floats()```. 
+  /// Adding a value of any other type, or changing the array with ```splice()```, 
+  /// ```retain()```, ```dedup_by()``` or ```sort_by()```, converts it to the ordinary 
+  /// representation for good.
+  pub fn new_typed(t:ElemType) -> DataArray {
+    let values = match t {
+      ElemType::Int => ArrayValues::Int(Vec::new()),
+      ElemType::Float => ArrayValues::Float(Vec::new()),
+    };
+    let data_ref = aheap().lock().push(values);
+    DataArray { data_ref }
+  }
+  
+  /// Returns the type of the values if this array is still packed (see ```new_typed()```).
+  pub fn elem_type(&self) -> Option<ElemType> {
+    let heap = &mut aheap().lock();
+    heap.get(self.data_ref).elem_type()
+  }
+  
+  /// Returns the values of this array if they are packed as ```i64``` (see ```new_typed()```). 
+  /// The array heap stays locked until the result is dropped, so do not access any array 
+  /// from this thread while holding it.
+  pub fn as_i64_slice(&self) -> Option<TypedSlice<i64>> {
+    let mut heap = aheap().lock();
+    let ArrayValues::Int(v) = heap.get(self.data_ref) else { return None; };
+    let slice = v.as_slice() as *const [i64];
+    Some(TypedSlice { _guard: heap, slice })
+  }
+  
+  /// Returns the values of this array if they are packed as ```f64``` (see ```new_typed()```). 
+  /// The array heap stays locked until the result is dropped, so do not access any array 
+  /// from this thread while holding it.
+  pub fn as_f64_slice(&self) -> Option<TypedSlice<f64>> {
+    let mut heap = aheap().lock();
+    let ArrayValues::Float(v) = heap.get(self.data_ref) else { return None; };
+    let slice = v.as_slice() as *const [f64];
+    Some(TypedSlice { _guard: heap, slice })
   }
   
   /// Prints the arrays currently stored in the heap
   #[cfg(not(feature="no_std_support"))]
   pub fn print_heap() {
-    println!("array {:?}", &aheap().lock().keys());
+    let heap = &aheap().lock();
+    println!("array {:?} fill {:.2}", heap.keys(), heap.fill_ratio());
+  }
+  
+  /// Prints the arrays currently stored in the heap to stderr, unless the heap is locked.
+  #[cfg(not(feature="no_std_support"))]
+  pub(crate) fn print_heap_at_panic() {
+    match aheap().try_lock_if_initialized() {
+      Some(heap) => eprintln!("array {:?} fill {:.2}", heap.keys(), heap.fill_ratio()),
+      None => eprintln!("array <locked>"),
+    }
   }
   
   /// Perform garbage collection. Arrays will not be removed from the heap until
   /// ```DataArray::gc()``` is called.
+  ///
+  /// Does nothing if called while this thread is already performing garbage collection.
   pub fn gc() {
+    let Some(_gc) = GcGuard::enter() else { return; };
     let oheap = &mut &mut oheap().lock();
     let aheap = &mut aheap().lock();
-    let adrop = &mut adrop().lock();
-    let mut i = adrop.len();
-    while i>0 {
-      i = i - 1;
-      let x = adrop.remove(0);
+    // Release the drop queue before deleting so that handles dropped meanwhile can be queued
+    let adrop = core::mem::take(&mut *adrop().lock());
+    for x in adrop {
       DataArray::delete(aheap, x, oheap);
     }
   }
+  
+  /// Perform garbage collection as for ```gc()```, unless a lock it needs is already held.
+  pub(crate) fn try_gc() {
+    let Some(_gc) = GcGuard::enter() else { return; };
+    let Some(mut oheap) = oheap().try_lock_if_initialized() else { return; };
+    let Some(mut aheap) = aheap().try_lock_if_initialized() else { return; };
+    let Some(mut queue) = adrop().try_lock_if_initialized() else { return; };
+    let adrop = core::mem::take(&mut *queue);
+    drop(queue);
+    for x in adrop {
+      DataArray::delete(&mut aheap, x, &mut oheap);
+    }
+  }
+}
+
+/// Replace the two space indentation produced by ```serde_json::to_string_pretty()``` with 
+/// ```indent``` spaces per level. Serialized strings never contain a raw newline, so every line 
+/// starts with indentation.
+#[cfg(feature="serde_support")]
+pub(crate) fn reindent(s:&str, indent:usize) -> String {
+  if indent == 2 { return s.to_string(); }
+  let mut out = String::new();
+  for (i, line) in s.split('\n').enumerate() {
+    if i > 0 { out.push('\n'); }
+    let trimmed = line.trim_start_matches(' ');
+    let depth = (line.len() - trimmed.len()) / 2;
+    for _ in 0..depth * indent { out.push(' '); }
+    out.push_str(trimmed);
+  }
+  out
+}
+
+/// An iterator over the values of a ```DataArray```, returned by ```DataArray::iter()```.
+pub struct DataArrayIter {
+  _arr: DataArray,
+  iter: std::vec::IntoIter<Data>,
+}
+
+impl Iterator for DataArrayIter {
+  type Item = Data;
+  
+  fn next(&mut self) -> Option<Data> {
+    self.iter.next()
+  }
+  
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    self.iter.size_hint()
+  }
+}
+
+impl IntoIterator for DataArray {
+  type Item = Data;
+  type IntoIter = DataArrayIter;
+  
+  fn into_iter(self) -> DataArrayIter {
+    let iter = self.objects().into_iter();
+    DataArrayIter { _arr: self, iter }
+  }
+}
+
+impl IntoIterator for &DataArray {
+  type Item = Data;
+  type IntoIter = DataArrayIter;
+  
+  fn into_iter(self) -> DataArrayIter {
+    self.iter()
+  }
+}
+
+/// Serializes the array as a sequence, as returned by ```to_json()```.
+#[cfg(feature="serde_support")]
+impl serde::Serialize for DataArray {
+  fn serialize<S: serde::Serializer>(&self, serializer:S) -> Result<S::Ok, S::Error> {
+    self.to_json().serialize(serializer)
+  }
+}
+
+/// Deserializes a sequence into a new array, as with ```from_json()```.
+#[cfg(feature="serde_support")]
+impl<'de> serde::Deserialize<'de> for DataArray {
+  fn deserialize<D: serde::Deserializer<'de>>(deserializer:D) -> Result<DataArray, D::Error> {
+    let value = Value::deserialize(deserializer)?;
+    if !value.is_array() { return Err(serde::de::Error::custom("expected a sequence for DataArray")); }
+    Ok(DataArray::from_json(value))
+  }
 }
 
 /// Adds this ```DataArray```'s data_ref to ODROP. Reference counts are adjusted when
-/// ```DataArray::gc()``` is called.
+/// ```DataArray::gc()``` is called, or here if the GC threshold is exceeded.
 impl Drop for DataArray {
   fn drop(&mut self) {
-    adrop().lock().push(self.data_ref);
+    let queued = {
+      let adrop = &mut adrop().lock();
+      adrop.push(self.data_ref);
+      adrop.len()
+    };
+    if crate::over_gc_threshold(queued) { crate::try_gc(); }
   }
 }
 
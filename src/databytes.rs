@@ -2,6 +2,15 @@ extern crate alloc;
 use core::cmp;
 use crate::heap::*;
 use crate::sharedmutex::*;
+use crate::ndataerror::*;
+use crate::data::*;
+#[cfg(feature="bytes_json")]
+use crate::dataobject::*;
+#[cfg(all(feature="bytes_json", feature="serde_support"))]
+use serde_json::Value;
+
+/// The standard base64 alphabet
+const BASE64:&[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
 
 /// Storage for runtime byte buffer values
 static mut BH:SharedMutex<Heap<DataStream>> = SharedMutex::new();
@@ -9,6 +18,25 @@ static mut BH:SharedMutex<Heap<DataStream>> = SharedMutex::new();
 /// Storage for runtime reference count reductions
 static mut BD:SharedMutex<Vec<usize>> = SharedMutex::new();
 
+/// Lookup table for CRC-32 (IEEE 802.3)
+const CRC32_TABLE:[u32; 256] = crc32_table();
+
+const fn crc32_table() -> [u32; 256] {
+  let mut table = [0u32; 256];
+  let mut i = 0;
+  while i < 256 {
+    let mut c = i as u32;
+    let mut k = 0;
+    while k < 8 {
+      c = if c & 1 != 0 { 0xEDB88320 ^ (c >> 1) } else { c >> 1 };
+      k += 1;
+    }
+    table[i] = c;
+    i += 1;
+  }
+  table
+}
+
 /// Implements a stream of bytes
 #[derive(Debug, Default)]
 pub struct DataStream {
@@ -48,6 +76,11 @@ impl DataStream {
     }
   }
   
+  /// Return the number of bytes currently held in the stream
+  pub(crate) fn current_len(&self) -> usize {
+    self.data.len()
+  }
+  
   /// Return a deep copy of the data stream
   pub fn deep_copy(&self) -> DataStream {
     DataStream {
@@ -68,18 +101,34 @@ pub fn bheap() -> &'static mut SharedMutex<Heap<DataStream>> {
   unsafe { &mut BH }
 }
 
-fn bdrop() -> &'static mut SharedMutex<Vec<usize>> {
+pub(crate) fn bdrop() -> &'static mut SharedMutex<Vec<usize>> {
   #[allow(static_mut_refs)]
   unsafe { &mut BD }
 }
 
 /// Represents a buffer of bytes (```Vec<u8>```)
-#[derive(Debug, Default)]
 pub struct DataBytes {
   /// The pointer to the array in the byte buffer heap.
   pub data_ref: usize,
 }
 
+/// Shows the number of bytes in the buffer as well as its data_ref. The length is omitted if it 
+/// cannot be read without waiting for a lock.
+impl core::fmt::Debug for DataBytes {
+  fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+    write!(f, "DataBytes {{ data_ref: {}, value: ", self.data_ref)?;
+    Data::DBytes(self.data_ref).debug_contents(f, &mut Vec::new())?;
+    write!(f, " }}")
+  }
+}
+
+/// The default for ```DataBytes``` is a new (empty) byte buffer, as returned by ```DataBytes::new()```.
+impl Default for DataBytes {
+  fn default() -> DataBytes {
+    DataBytes::new()
+  }
+}
+
 impl Clone for DataBytes{
   /// Returns another DataBytes pointing to the same value.
   fn clone(&self) -> Self {
@@ -94,8 +143,14 @@ impl Clone for DataBytes{
 impl DataBytes {
   /// Initialize global storage of byte buffers. Call only once at startup.
   pub fn init() -> ((u64, u64),(u64, u64)){
+    DataBytes::init_with_heap(Heap::new())
+  }
+  
+  /// Initialize global storage of byte buffers using the given (empty) heap, such as one created 
+  /// with ```Heap::in_region()```. Call only once at startup, instead of ```init()```.
+  pub fn init_with_heap(heap:Heap<DataStream>) -> ((u64, u64),(u64, u64)){
     unsafe {
-      BH.set(Heap::new());
+      BH.set(heap);
       BD.set(Vec::new());
     }
     DataBytes::share()
@@ -117,6 +172,12 @@ impl DataBytes {
     }
   }
   
+  /// Set how this process waits when the byte buffer storage is locked by another thread.
+  pub fn set_contention_policy(policy:ContentionPolicy) {
+    bheap().set_contention_policy(policy);
+    bdrop().set_contention_policy(policy);
+  }
+  
   /// Create a new (empty) byte buffer.
   pub fn new() -> DataBytes {
     let data_ref = &mut bheap().lock().push(DataStream::new());
@@ -133,6 +194,48 @@ impl DataBytes {
     };
   }
   
+  /// Create a new byte buffer containing the UTF-8 encoding of the given string.
+  #[allow(clippy::should_implement_trait)]
+  pub fn from_str(s:&str) -> DataBytes {
+    let data_ref = bheap().lock().push(DataStream::from_bytes(s.as_bytes().to_vec()));
+    DataBytes {
+      data_ref,
+    }
+  }
+  
+  /// Create a new byte buffer containing the current bytes of each of the given buffers, in 
+  /// order. The bytes heap is locked once for the whole operation.
+  pub fn concat(parts:&[DataBytes]) -> DataBytes {
+    let heap = &mut bheap().lock();
+    let len = parts.iter().map(|b| heap.get(b.data_ref).data.len()).sum();
+    let mut buf = Vec::with_capacity(len);
+    for b in parts {
+      buf.extend_from_slice(&heap.get(b.data_ref).data);
+    }
+    DataBytes {
+      data_ref: heap.push(DataStream::from_bytes(buf)),
+    }
+  }
+  
+  /// Returns the bytes currently in the buffer decoded as UTF-8, or an error if they are not
+  /// valid UTF-8.
+  pub fn as_utf8_string(&self) -> Result<String, NDataError> {
+    let heap = &mut bheap().lock();
+    let vec = heap.get(self.data_ref);
+    match core::str::from_utf8(&vec.data) {
+      Ok(s) => Ok(s.to_string()),
+      Err(e) => Err(NDataError::InvalidUtf8 { valid_up_to: e.valid_up_to() }),
+    }
+  }
+  
+  /// Returns the bytes currently in the buffer decoded as UTF-8, replacing invalid sequences 
+  /// with ```U+FFFD```.
+  pub fn as_utf8_string_lossy(&self) -> String {
+    let heap = &mut bheap().lock();
+    let vec = heap.get(self.data_ref);
+    String::from_utf8_lossy(&vec.data).into_owned()
+  }
+  
   /// Returns a copy of the underlying vec of bytes in the array
   pub fn get_data(&self) -> Vec<u8> {
     let heap = &mut bheap().lock();
@@ -163,6 +266,86 @@ impl DataBytes {
     d
   }
   
+  /// Returns up to the requested number of bytes from the front of the stream without removing
+  /// them. Panics if the stream is closed for reading.
+  pub fn peek(&self, n:usize) -> Vec<u8> {
+    match self.try_peek(n) {
+      Ok(d) => d,
+      Err(e) => panic!("{}", e),
+    }
+  }
+  
+  /// Returns up to the requested number of bytes as for ```peek()```, or 
+  /// ```NDataError::StreamClosed``` if the stream is closed for reading.
+  pub fn try_peek(&self, n:usize) -> Result<Vec<u8>, NDataError> {
+    let heap = &mut bheap().lock();
+    let vec = heap.get(self.data_ref);
+    if !vec.read_open { return Err(NDataError::StreamClosed); }
+    let n = cmp::min(n, vec.data.len());
+    Ok(vec.data[0..n].to_vec())
+  }
+  
+  /// Removes and returns all of the bytes currently in the stream. As with ```read()```, the 
+  /// stream is closed for reading once it is empty and closed for writing. Panics if the stream
+  /// is closed for reading.
+  pub fn read_all(&self) -> Vec<u8> {
+    match self.try_read_all() {
+      Ok(d) => d,
+      Err(e) => panic!("{}", e),
+    }
+  }
+  
+  /// Removes and returns all of the bytes as for ```read_all()```, or 
+  /// ```NDataError::StreamClosed``` if the stream is closed for reading.
+  pub fn try_read_all(&self) -> Result<Vec<u8>, NDataError> {
+    let heap = &mut bheap().lock();
+    let vec = heap.get(self.data_ref);
+    if !vec.read_open { return Err(NDataError::StreamClosed); }
+    let d = core::mem::take(&mut vec.data);
+    if !vec.write_open {
+      vec.read_open = false;
+    }
+    Ok(d)
+  }
+  
+  /// Removes and returns the next line from the stream, without the trailing ```"\n"``` or 
+  /// ```"\r\n"```. Invalid UTF-8 is replaced with ```U+FFFD```. 
+  ///
+  /// Returns ```None``` if no complete line is available yet while the stream is still open 
+  /// for writing, or if the stream has been read to the end. Once writing is closed, any 
+  /// remaining bytes without a trailing newline are returned as the last line.
+  pub fn read_line(&self) -> Option<String> {
+    self.take_line().map(|line| String::from_utf8_lossy(&line).into_owned())
+  }
+  
+  /// Removes and returns the next line from the stream as for ```read_line()```, or an error
+  /// if the line is not valid UTF-8. The line is consumed either way.
+  pub fn try_read_line(&self) -> Option<Result<String, NDataError>> {
+    self.take_line().map(|line| String::from_utf8(line).map_err(|e| NDataError::InvalidUtf8 { valid_up_to: e.utf8_error().valid_up_to() }))
+  }
+  
+  fn take_line(&self) -> Option<Vec<u8>> {
+    let heap = &mut bheap().lock();
+    let vec = heap.get(self.data_ref);
+    if !vec.read_open { return None; }
+    let line = match vec.data.iter().position(|b| *b == b'\n') {
+      Some(n) => {
+        let mut line: Vec<u8> = vec.data.drain(0..n+1).collect();
+        line.pop();
+        if line.last() == Some(&b'\r') { line.pop(); }
+        line
+      },
+      None => {
+        if vec.write_open || vec.data.is_empty() { return None; }
+        core::mem::take(&mut vec.data)
+      },
+    };
+    if !vec.write_open && vec.data.is_empty() {
+      vec.read_open = false;
+    }
+    Some(line)
+  }
+  
   /// Sets the underlying vec of bytes in the array
   pub fn set_data(&self, buf:&Vec<u8>) {
     let heap = &mut bheap().lock();
@@ -269,10 +452,20 @@ impl DataBytes {
     let heap = &mut bheap().lock();
     let bytes = heap.get(self.data_ref);
     let vec = bytes.deep_copy();
-    let data_ref = &mut bheap().lock().push(vec);
-    return DataBytes {
-      data_ref: *data_ref,
-    };
+    DataBytes {
+      data_ref: heap.push(vec),
+    }
+  }
+  
+  /// Returns the CRC-32 (IEEE 802.3) checksum of the bytes currently in the buffer.
+  pub fn crc32(&self) -> u32 {
+    let heap = &mut bheap().lock();
+    let bytes = heap.get(self.data_ref);
+    let mut crc = !0u32;
+    for b in &bytes.data {
+      crc = CRC32_TABLE[((crc ^ *b as u32) & 0xFF) as usize] ^ (crc >> 8);
+    }
+    !crc
   }
   
   /// Returns the byte buffer as a hexidecimal String.
@@ -285,31 +478,219 @@ impl DataBytes {
     strs.join(" ")    
   }
   
+  /// Returns the byte buffer as a base64 string, using the standard alphabet with padding.
+  pub fn to_base64(&self) -> String {
+    let heap = &mut bheap().lock();
+    let bytes = &heap.get(self.data_ref).data;
+    let mut s = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+      let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+      let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+      for i in 0..4 {
+        if i <= chunk.len() { s.push(BASE64[(n >> (18 - 6 * i) & 63) as usize] as char); }
+        else { s.push('='); }
+      }
+    }
+    s
+  }
+  
+  /// Create a new byte buffer from a base64 string using the standard alphabet. Padding is 
+  /// optional and whitespace is ignored.
+  pub fn from_base64(s:&str) -> Result<DataBytes, NDataError> {
+    let mut buf = Vec::with_capacity(s.len() / 4 * 3);
+    let mut n:u32 = 0;
+    let mut bits = 0;
+    let mut padding = 0;
+    for (i, c) in s.bytes().enumerate() {
+      if c.is_ascii_whitespace() { continue; }
+      if c == b'=' {
+        padding += 1;
+        if padding > 2 { return Err(NDataError::InvalidBase64 { position: i }); }
+        continue;
+      }
+      let Some(d) = BASE64.iter().position(|x| *x == c) else {
+        return Err(NDataError::InvalidBase64 { position: i });
+      };
+      if padding > 0 { return Err(NDataError::InvalidBase64 { position: i }); }
+      n = n << 6 | d as u32;
+      bits += 6;
+      if bits >= 8 {
+        bits -= 8;
+        buf.push((n >> bits) as u8);
+        n &= (1 << bits) - 1;
+      }
+    }
+    // A single leftover character cannot encode a whole byte
+    if bits >= 6 { return Err(NDataError::InvalidBase64 { position: s.len() }); }
+    Ok(DataBytes::from_bytes(&buf))
+  }
+  
+  /// Returns the byte buffer represented by the given object if it is a tagged value with the 
+  /// tag ```BYTES_TAG``` and a valid base64 value, as written to JSON when the "bytes_json" 
+  /// feature is enabled.
+  #[cfg(feature="bytes_json")]
+  pub fn from_tagged(o:&DataObject) -> Option<DataBytes> {
+    match o.as_tagged()? {
+      (tag, Data::DString(s)) if tag == BYTES_TAG => DataBytes::from_base64(&s).ok(),
+      _ => None,
+    }
+  }
+  
+  /// Returns the byte buffer represented by the given ```serde_json::Value``` as for 
+  /// ```from_tagged()```.
+  #[cfg(all(feature="bytes_json", feature="serde_support"))]
+  pub(crate) fn from_tagged_json(v:&Value) -> Option<DataBytes> {
+    if v.get(TAG_KEY)?.as_str()? != BYTES_TAG { return None; }
+    DataBytes::from_base64(v.get(TAG_VALUE_KEY)?.as_str()?).ok()
+  }
+  
+  /// Returns the JSON representation of this byte buffer: a tagged value when the 
+  /// "bytes_json" feature is enabled, otherwise a hexidecimal string.
+  #[cfg(feature="serde_support")]
+  pub(crate) fn to_json(&self) -> serde_json::Value {
+    #[cfg(feature="bytes_json")]
+    return serde_json::json!({ TAG_KEY: BYTES_TAG, TAG_VALUE_KEY: self.to_base64() });
+    #[cfg(not(feature="bytes_json"))]
+    serde_json::json!(self.to_hex_string())
+  }
+  
   /// Prints the byte buffers currently stored in the heap
   #[cfg(not(feature="no_std_support"))]
   pub fn print_heap() {
-    println!("bytes {:?}", &mut bheap().lock().keys());
+    let heap = &bheap().lock();
+    println!("bytes {:?} fill {:.2}", heap.keys(), heap.fill_ratio());
+  }
+  
+  /// Prints the byte buffers currently stored in the heap to stderr, unless the heap is locked.
+  #[cfg(not(feature="no_std_support"))]
+  pub(crate) fn print_heap_at_panic() {
+    match bheap().try_lock_if_initialized() {
+      Some(heap) => eprintln!("bytes {:?} fill {:.2}", heap.keys(), heap.fill_ratio()),
+      None => eprintln!("bytes <locked>"),
+    }
   }
   
   /// Perform garbage collection. Byte buffers will not be removed from the heap until
   /// ```DataBytes::gc()``` is called.
+  ///
+  /// Does nothing if called while this thread is already performing garbage collection.
   pub fn gc() {
+    let Some(_gc) = GcGuard::enter() else { return; };
     let bheap = &mut bheap().lock();
-    let bdrop = &mut bdrop().lock();
-    let mut i = bdrop.len();
-    while i>0 {
-      i = i - 1;
-      let x = bdrop.remove(0);
+    let bdrop = core::mem::take(&mut *bdrop().lock());
+    for x in bdrop {
       bheap.decr(x);
     }
   }
+  
+  /// Perform garbage collection as for ```gc()```, unless a lock it needs is already held.
+  pub(crate) fn try_gc() {
+    let Some(_gc) = GcGuard::enter() else { return; };
+    let Some(mut bheap) = bheap().try_lock_if_initialized() else { return; };
+    let Some(mut queue) = bdrop().try_lock_if_initialized() else { return; };
+    let bdrop = core::mem::take(&mut *queue);
+    drop(queue);
+    for x in bdrop {
+      bheap.decr(x);
+    }
+  }
+}
+
+/// Reads drain bytes from the front of the stream, as with ```DataBytes::read()```. Returns
+/// ```Ok(0)``` once the stream is closed for reading, and ```ErrorKind::WouldBlock``` if it is 
+/// empty but still open for writing.
+///
+/// Reading never waits for a writer. Functions that read to the end, such as 
+/// ```std::io::copy()``` and ```read_to_end()```, fail with ```WouldBlock``` as soon as they 
+/// catch up with the writer, so call ```close_write()``` before handing the stream to them, or 
+/// retry on ```WouldBlock``` when reading while another thread is still writing.
+///
+/// Because ```DataBytes``` has inherent ```read()``` and ```write()``` methods, call these 
+/// through the trait (```std::io::Read::read(&mut b, buf)```) or pass the ```DataBytes``` to 
+/// functions taking ```impl Read``` or ```impl Write```.
+#[cfg(not(feature="no_std_support"))]
+impl std::io::Read for DataBytes {
+  fn read(&mut self, buf:&mut [u8]) -> std::io::Result<usize> {
+    let heap = &mut bheap().lock();
+    let vec = heap.get(self.data_ref);
+    if !vec.read_open || buf.is_empty() { return Ok(0); }
+    if vec.data.is_empty() && vec.write_open { return Err(std::io::ErrorKind::WouldBlock.into()); }
+    let n = cmp::min(buf.len(), vec.data.len());
+    buf[..n].copy_from_slice(&vec.data[..n]);
+    vec.data.drain(0..n);
+    if !vec.write_open && vec.data.is_empty() {
+      vec.read_open = false;
+    }
+    Ok(n)
+  }
+}
+
+/// Writes append to the stream, as with ```DataBytes::write()```. Returns 
+/// ```ErrorKind::WriteZero``` if the stream is closed. Flushing does nothing.
+#[cfg(not(feature="no_std_support"))]
+impl std::io::Write for DataBytes {
+  fn write(&mut self, buf:&[u8]) -> std::io::Result<usize> {
+    if buf.is_empty() { return Ok(0); }
+    if DataBytes::write(self, buf) { Ok(buf.len()) }
+    else { Err(std::io::Error::new(std::io::ErrorKind::WriteZero, "Attempt to write to closed data stream")) }
+  }
+  
+  fn flush(&mut self) -> std::io::Result<()> {
+    Ok(())
+  }
+}
+
+/// Serializes the bytes as for ```to_json()```: a tagged value when the "bytes_json" feature is
+/// enabled, otherwise a hexidecimal string, as returned by ```to_hex_string()```.
+#[cfg(feature="serde_support")]
+impl serde::Serialize for DataBytes {
+  fn serialize<S: serde::Serializer>(&self, serializer:S) -> Result<S::Ok, S::Error> {
+    #[cfg(feature="bytes_json")]
+    {
+      self.to_json().serialize(serializer)
+    }
+    #[cfg(not(feature="bytes_json"))]
+    {
+      serializer.serialize_str(&self.to_hex_string())
+    }
+  }
+}
+
+/// Deserializes a new byte buffer from the representation written by ```serialize()```: a 
+/// tagged value when the "bytes_json" feature is enabled, otherwise a hexidecimal string, in 
+/// which whitespace between digits is ignored.
+#[cfg(feature="serde_support")]
+impl<'de> serde::Deserialize<'de> for DataBytes {
+  fn deserialize<D: serde::Deserializer<'de>>(deserializer:D) -> Result<DataBytes, D::Error> {
+    #[cfg(feature="bytes_json")]
+    {
+      let v = <Value as serde::Deserialize>::deserialize(deserializer)?;
+      DataBytes::from_tagged_json(&v).ok_or_else(|| serde::de::Error::custom("expected a tagged byte buffer"))
+    }
+    #[cfg(not(feature="bytes_json"))]
+    {
+      let s = <String as serde::Deserialize>::deserialize(deserializer)?;
+      let digits:Vec<u32> = s.chars()
+                             .filter(|c| !c.is_whitespace())
+                             .map(|c| c.to_digit(16).ok_or_else(|| serde::de::Error::custom(format!("invalid hex digit {:?}", c))))
+                             .collect::<Result<_, D::Error>>()?;
+      if !digits.len().is_multiple_of(2) { return Err(serde::de::Error::custom("odd number of hex digits")); }
+      let buf:Vec<u8> = digits.chunks(2).map(|d| (d[0] * 16 + d[1]) as u8).collect();
+      Ok(DataBytes::from_bytes(&buf))
+    }
+  }
 }
 
 /// Adds this ```DataBytes```'s data_ref to BDROP. Reference counts are adjusted when
-/// ```DataBytes::gc()``` is called.
+/// ```DataBytes::gc()``` is called, or here if the GC threshold is exceeded.
 impl Drop for DataBytes {
   fn drop(&mut self) {
-    bdrop().lock().push(self.data_ref);
+    let queued = {
+      let bdrop = &mut bdrop().lock();
+      bdrop.push(self.data_ref);
+      bdrop.len()
+    };
+    if crate::over_gc_threshold(queued) { crate::try_gc(); }
   }
 }
 
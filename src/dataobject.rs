@@ -5,6 +5,9 @@ use crate::data::*;
 use crate::dataarray::*;
 use crate::databytes::*;
 use crate::sharedmutex::*;
+use crate::ndataerror::*;
+use core::sync::atomic::AtomicBool;
+use core::sync::atomic::Ordering;
 
 #[cfg(feature="serde_support")]
 use serde_json::Value;
@@ -27,18 +30,126 @@ pub fn oheap() -> &'static mut SharedMutex<Heap<HashMap<String,Data>>> {
   unsafe { &mut OH }
 }
 
-fn odrop() -> &'static mut SharedMutex<Vec<usize>> {
+pub(crate) fn odrop() -> &'static mut SharedMutex<Vec<usize>> {
   #[allow(static_mut_refs)]
   unsafe { &mut OD }
 }
 
+/// Callbacks registered with ```DataObject::on_change()```, as ```(data_ref, key, callback)```. 
+/// Function pointers are only valid in the process that registered them, so this storage is
+/// never shared with other processes.
+static mut OC:SharedMutex<Observers> = SharedMutex::new();
+
+type Observers = Vec<(usize, String, fn(&str, &Data))>;
+
+/// Set once any callback has been registered, so that changes can skip the registry until then
+static OBSERVED:AtomicBool = AtomicBool::new(false);
+
+fn observers() -> &'static mut SharedMutex<Observers> {
+  #[allow(static_mut_refs)]
+  unsafe { &mut OC }
+}
+
+/// Call the callbacks registered for the given object and key. Must not be called while holding
+/// the object heap lock, so that callbacks are free to read and modify data.
+fn fire_change(data_ref:usize, key:&str, data:&Data) {
+  if !OBSERVED.load(Ordering::Acquire) { return; }
+  let callbacks: Vec<fn(&str, &Data)> = observers().lock().iter()
+    .filter(|(i, k, _cb)| *i == data_ref && k == key)
+    .map(|(_i, _k, cb)| *cb)
+    .collect();
+  for cb in callbacks {
+    cb(key, data);
+  }
+}
+
+/// Discard all callbacks registered with ```DataObject::on_change()```
+pub(crate) fn clear_observers() {
+  if !observers().is_initialized() { return; }
+  observers().lock().clear();
+  OBSERVED.store(false, Ordering::Release);
+}
+
+/// The key holding the tag of a tagged value
+pub const TAG_KEY:&str = "$type";
+
+/// The key holding the value of a tagged value
+pub const TAG_VALUE_KEY:&str = "$value";
+
+/// The tag of a timestamp stored by ```put_timestamp()```
+pub const TIMESTAMP_TAG:&str = "timestamp";
+
+/// The tag of a byte buffer serialized as JSON when the "bytes_json" feature is enabled. The 
+/// value is the bytes encoded as base64.
+pub const BYTES_TAG:&str = "bytes";
+
+/// Whether timestamps are serialized as RFC 3339 strings
+static TIMESTAMPS_AS_RFC3339:AtomicBool = AtomicBool::new(false);
+
+/// Set whether timestamps stored with ```DataObject::put_timestamp()``` are serialized as RFC 3339
+/// strings such as ```"2024-01-01T00:00:00Z"``` instead of tagged objects. Defaults to ```false```.
+///
+/// Note that timestamps serialized as strings are parsed back as plain strings.
+pub fn set_timestamps_as_rfc3339(b:bool) {
+  TIMESTAMPS_AS_RFC3339.store(b, Ordering::Relaxed);
+}
+
+/// Returns ```true``` if timestamps are serialized as RFC 3339 strings.
+pub fn is_timestamps_as_rfc3339() -> bool {
+  TIMESTAMPS_AS_RFC3339.load(Ordering::Relaxed)
+}
+
+/// Format seconds and nanoseconds since the Unix epoch as an RFC 3339 string in UTC.
+pub fn rfc3339(secs:i64, nanos:u32) -> String {
+  let days = secs.div_euclid(86400);
+  let t = secs.rem_euclid(86400);
+  // Convert days since the epoch to a civil date (Howard Hinnant's algorithm)
+  let z = days + 719468;
+  let era = z.div_euclid(146097);
+  let doe = z.rem_euclid(146097);
+  let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+  let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+  let mp = (5 * doy + 2) / 153;
+  let d = doy - (153 * mp + 2) / 5 + 1;
+  let m = if mp < 10 { mp + 3 } else { mp - 9 };
+  let y = yoe + era * 400 + if m <= 2 { 1 } else { 0 };
+  let mut s = format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}", y, m, d, t / 3600, t % 3600 / 60, t % 60);
+  if nanos > 0 { s += &format!(".{:09}", nanos); }
+  s += "Z";
+  s
+}
+
+/// Returns the value as an RFC 3339 string if it is a timestamp and timestamps are serialized
+/// as strings.
+pub(crate) fn timestamp_string(p:&Data) -> Option<String> {
+  if !is_timestamps_as_rfc3339() || !p.is_object() { return None; }
+  let (secs, nanos) = p.object().as_timestamp()?;
+  Some(rfc3339(secs, nanos))
+}
+
 /// Represents a map of type ```<String, ndata.Data>```. 
-#[derive(Debug, Default)]
 pub struct DataObject {
   /// The pointer to the object in the object heap.
   pub data_ref: usize,
 }
 
+/// Shows the contents of the object as well as its data_ref. Object keys are written in sorted
+/// order, and contents that cannot be read without waiting for a lock are omitted.
+impl core::fmt::Debug for DataObject {
+  fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+    write!(f, "DataObject {{ data_ref: {}, value: ", self.data_ref)?;
+    Data::DObject(self.data_ref).debug_contents(f, &mut Vec::new())?;
+    write!(f, " }}")
+  }
+}
+
+/// The default for ```DataObject``` is a new (empty) object, as returned by ```DataObject::new()```.
+impl Default for DataObject {
+  fn default() -> DataObject {
+    DataObject::new()
+  }
+}
+
 impl Clone for DataObject{
   /// Returns another DataObject pointing to the same value.
   fn clone(&self) -> Self {
@@ -53,10 +164,17 @@ impl Clone for DataObject{
 impl DataObject {
   /// Initialize global storage of objects. Call only once at startup.
   pub fn init() -> ((u64, u64),(u64, u64)){
+    DataObject::init_with_heap(Heap::new())
+  }
+  
+  /// Initialize global storage of objects using the given (empty) heap, such as one created 
+  /// with ```Heap::in_region()```. Call only once at startup, instead of ```init()```.
+  pub fn init_with_heap(heap:Heap<HashMap<String,Data>>) -> ((u64, u64),(u64, u64)){
     unsafe {
-      OH.set(Heap::new());
+      OH.set(heap);
       OD.set(Vec::new());
     }
+    observers().set_if_needed(Vec::new());
     DataObject::share()
   }
   
@@ -74,6 +192,13 @@ impl DataObject {
       OH.mirror(q.0, q.1);
       OD.mirror(r.0, r.1);
     }
+    observers().set_if_needed(Vec::new());
+  }
+  
+  /// Set how this process waits when the object storage is locked by another thread.
+  pub fn set_contention_policy(policy:ContentionPolicy) {
+    oheap().set_contention_policy(policy);
+    odrop().set_contention_policy(policy);
   }
   
   /// Create a new (empty) object.
@@ -84,6 +209,21 @@ impl DataObject {
     };
   }
   
+  /// Create a new (empty) object with space for at least ```capacity``` keys, so that it does 
+  /// not reallocate until it grows past that size.
+  pub fn with_capacity(capacity:usize) -> DataObject {
+    let data_ref = &mut oheap().lock().push(HashMap::<String,Data>::with_capacity(capacity));
+    DataObject {
+      data_ref: *data_ref,
+    }
+  }
+  
+  /// Reserve space for at least ```additional``` more keys in this object.
+  pub fn reserve(&mut self, additional:usize) {
+    let oheap = &mut oheap().lock();
+    oheap.get(self.data_ref).reserve(additional);
+  }
+  
   /// Get a reference to the object from the heap
   pub fn get(data_ref: usize) -> DataObject {
     let o = DataObject{
@@ -121,6 +261,70 @@ impl DataObject {
     return object_to_string(self.clone());
   }  
   
+  /// Create an indented JSON string from a DataObject, with ```indent``` spaces per level of 
+  /// nesting.
+  pub fn to_string_pretty(&self, indent:usize) -> String {
+    #[cfg(feature="serde_support")]
+    return reindent(&serde_json::to_string_pretty(&self.to_json()).unwrap(), indent);
+    #[cfg(not(feature="serde_support"))]
+    return object_to_string_pretty(self.clone(), indent, false);
+  }
+  
+  /// Create a new DataObject from a URL query string such as ```a=1&b=two```. Keys and values 
+  /// are percent-decoded and stored as strings. A key that appears more than once is stored as
+  /// an array of its values.
+  pub fn from_query_string(s:&str) -> DataObject {
+    let mut o = DataObject::new();
+    let s = s.strip_prefix('?').unwrap_or(s);
+    for pair in s.split('&') {
+      if pair.is_empty() { continue; }
+      let (key, val) = match pair.split_once('=') {
+        Some((k, v)) => (percent_decode(k), percent_decode(v)),
+        None => (percent_decode(pair), "".to_string()),
+      };
+      if !o.has(&key) { o.put_string(&key, &val); }
+      else {
+        let old = o.get_property(&key);
+        if old.is_array() { old.array().push_string(&val); }
+        else {
+          let mut a = DataArray::new();
+          a.push_property(old);
+          a.push_string(&val);
+          o.put_array(&key, a);
+        }
+      }
+    }
+    o
+  }
+  
+  /// Create a URL query string such as ```a=1&b=two``` from a DataObject. Keys and values are
+  /// percent-encoded. Array values are written as one pair per element.
+  pub fn to_query_string(&self) -> String {
+    let mut pairs = Vec::<String>::new();
+    for (key, val) in self.objects() {
+      let key = percent_encode(&key);
+      if val.is_array() {
+        for v in val.array().objects() {
+          pairs.push(key.to_owned() + "=" + &percent_encode(&Data::as_string(v)));
+        }
+      }
+      else {
+        pairs.push(key + "=" + &percent_encode(&Data::as_string(val)));
+      }
+    }
+    pairs.join("&")
+  }
+  
+  /// Write this object as JSON to the given writer, without first building the whole JSON
+  /// string in memory.
+  #[cfg(not(feature="no_std_support"))]
+  pub fn write_json<W: std::io::Write>(&self, w:&mut W) -> std::io::Result<()> {
+    #[cfg(feature="serde_support")]
+    return serde_json::to_writer(w, &JsonObject(self)).map_err(|e| e.into());
+    #[cfg(not(feature="serde_support"))]
+    return write_object_io(w, self);
+  }
+  
   /// Create a new object from the ```serde_json::Value```.
   #[cfg(feature="serde_support")]
   pub fn from_json(value:Value) -> DataObject {
@@ -130,7 +334,11 @@ impl DataObject {
       else if val.is_boolean() { o.put_boolean(key, val.as_bool().unwrap()); }
       else if val.is_i64() { o.put_int(key, val.as_i64().unwrap()); }
       else if val.is_f64() { o.put_float(key, val.as_f64().unwrap()); }
-      else if val.is_object() { o.put_object(key, DataObject::from_json(val.to_owned())); }
+      else if val.is_object() {
+        #[cfg(feature="bytes_json")]
+        if let Some(b) = DataBytes::from_tagged_json(val) { o.put_bytes(key, b); continue; }
+        o.put_object(key, DataObject::from_json(val.to_owned()));
+      }
       else if val.is_array() { o.put_array(key, DataArray::from_json(val.to_owned())); }      
       else if val.is_null() { o.put_null(key); }
       else { println!("Unknown type {}", val) };
@@ -139,6 +347,9 @@ impl DataObject {
   }
   
   /// Return the object as a ```serde_json::Value```.
+  ///
+  /// Floats that are ```NaN``` or infinite become ```null```, since JSON cannot represent them.
+  /// Timestamps become strings if ```set_timestamps_as_rfc3339()``` is enabled.
   #[cfg(feature="serde_support")]
   pub fn to_json(&self) -> Value {
     let mut val = json!({});
@@ -147,9 +358,10 @@ impl DataObject {
       else if old.is_float() { val[keystr] = json!(self.get_float(&keystr)); }
       else if old.is_boolean() { val[keystr] = json!(self.get_boolean(&keystr)); }
       else if old.is_string() { val[keystr] = json!(self.get_string(&keystr)); }
+      else if let Some(ts) = timestamp_string(&old) { val[keystr] = json!(ts); }
       else if old.is_object() { val[keystr] = self.get_object(&keystr).to_json(); }
       else if old.is_array() { val[keystr] = self.get_array(&keystr).to_json(); }
-      else if old.is_bytes() { val[keystr] = json!(self.get_bytes(&keystr).to_hex_string()); }
+      else if old.is_bytes() { val[keystr] = self.get_bytes(&keystr).to_json(); }
       else { val[keystr] = json!(null); }
     }
     val
@@ -192,6 +404,206 @@ impl DataObject {
     o
   }
   
+  /// Copy every key value pair from ```other``` into this object. Where both objects have an 
+  /// object for the same key, the two are merged in turn rather than replaced. Equivalent to
+  /// ```merge_with(other, false)```.
+  pub fn merge(&mut self, other:&DataObject) {
+    self.merge_with(other, false);
+  }
+  
+  /// Copy every key value pair from ```other``` into this object, merging nested objects as
+  /// for ```merge()```. If ```merge_arrays``` is ```true```, where both objects have an array for
+  /// the same key the values of the array in ```other``` are appended to the array in this 
+  /// object, otherwise the array is replaced. Values from ```other``` are shared, not copied.
+  pub fn merge_with(&mut self, other:&DataObject, merge_arrays:bool) {
+    self.merge_in(other, merge_arrays, &mut Vec::new());
+  }
+  
+  fn merge_in(&mut self, other:&DataObject, merge_arrays:bool, seen:&mut Vec<(usize, usize)>) {
+    if self.data_ref == other.data_ref || seen.contains(&(self.data_ref, other.data_ref)) { return; }
+    seen.push((self.data_ref, other.data_ref));
+    for (k, v) in other.objects() {
+      match (self.try_get_property(&k), &v) {
+        (Ok(Data::DObject(i)), Data::DObject(j)) => DataObject::get(i).merge_in(&DataObject::get(*j), merge_arrays, seen),
+        (Ok(Data::DArray(i)), Data::DArray(j)) if merge_arrays => DataArray::get(i).join(DataArray::get(*j)),
+        _ => self.set_property(&k, v),
+      }
+    }
+  }
+  
+  /// Add a deep copy of each value in ```defaults``` whose key is missing from this object. 
+  /// Where both objects have an object for the same key, the defaults are applied to that 
+  /// object in turn. Existing values are never replaced.
+  pub fn apply_defaults(&mut self, defaults:&DataObject) {
+    self.apply_defaults_in(defaults, &mut Vec::new());
+  }
+  
+  fn apply_defaults_in(&mut self, defaults:&DataObject, seen:&mut Vec<(usize, usize)>) {
+    if seen.contains(&(self.data_ref, defaults.data_ref)) { return; }
+    seen.push((self.data_ref, defaults.data_ref));
+    for (k, v) in defaults.objects() {
+      match self.try_get_property(&k) {
+        Ok(Data::DObject(i)) => {
+          if let Data::DObject(j) = v {
+            DataObject::get(i).apply_defaults_in(&DataObject::get(j), seen);
+          }
+        },
+        Ok(_) => {},
+        Err(_) => self.set_property(&k, v.deep_clone()),
+      }
+    }
+  }
+  
+  /// Copy the values for the given keys from ```source``` into this object, replacing any 
+  /// existing values. Keys that ```source``` does not contain are skipped. The values are read
+  /// from ```source``` under a single lock.
+  pub fn copy_keys_from(&mut self, source:&DataObject, keys:&[&str]) {
+    let values: Vec<(&str, Data)> = {
+      let oheap = &mut oheap().lock();
+      let map = oheap.get(source.data_ref);
+      keys.iter().filter_map(|k| map.get(*k).map(|v| (*k, v.clone()))).collect()
+    };
+    for (k, v) in values {
+      self.set_property(k, v);
+    }
+  }
+  
+  /// Replace the contents of ```target``` with a deep copy of the contents of this object. 
+  /// Unlike ```deep_copy()```, no new object is created, so existing references to 
+  /// ```target``` see the new contents. Callbacks registered on ```target``` with 
+  /// ```on_change()``` are called for each copied key, and with ```DNull``` for each key that 
+  /// was removed and not copied.
+  pub fn deep_copy_into(&self, target:&mut DataObject) {
+    let copy = self.deep_copy();
+    let removed = {
+      let oheap = &mut oheap().lock();
+      let mut removed = Vec::new();
+      for (k, v) in core::mem::take(oheap.get(target.data_ref)) {
+        v.queue_decr();
+        removed.push(k);
+      }
+      removed
+    };
+    target.merge_into(copy);
+    if OBSERVED.load(Ordering::Acquire) {
+      for k in removed.iter().filter(|k| !target.has(k)) {
+        fire_change(target.data_ref, k, &Data::DNull);
+      }
+    }
+  }
+  
+  /// Returns a new ```DataArray``` containing the values in this object, ordered by their keys.
+  /// Keys are compared lexicographically by byte value.
+  pub fn values_sorted(&self) -> DataArray {
+    let mut list = self.objects();
+    list.sort_by(|a, b| a.0.cmp(&b.0));
+    let mut a = DataArray::new();
+    for (_k, v) in list {
+      a.push_property(v);
+    }
+    a
+  }
+  
+  /// Returns a new ```DataArray``` containing a two element ```[key, value]``` array for
+  /// each key value pair in this object.
+  pub fn to_pairs_array(&self) -> DataArray {
+    let mut a = DataArray::new();
+    for (k,v) in self.objects() {
+      let mut pair = DataArray::new();
+      pair.push_string(&k);
+      pair.push_property(v);
+      a.push_array(pair);
+    }
+    a
+  }
+
+  /// Create a new DataObject from an array of two element ```[key, value]``` arrays, such as
+  /// one returned by ```to_pairs_array()```. Panics if an element is not a pair with a string key.
+  pub fn from_pairs_array(a:&DataArray) -> DataObject {
+    let mut o = DataObject::new();
+    for pair in a.objects() {
+      let pair = pair.array();
+      if pair.len() != 2 { panic!("Expected a [key, value] pair but got: {}", pair.to_string()); }
+      o.set_property(&pair.get_string(0), pair.get_property(1));
+    }
+    o
+  }
+
+  /// Create a new object representing a tagged value, such as a variant of a Rust enum. The tag
+  /// is stored under ```TAG_KEY``` and the value under ```TAG_VALUE_KEY```, so the tag survives
+  /// serialization to and from JSON.
+  pub fn tagged(tag:&str, value:Data) -> DataObject {
+    DataObject::tagged_with(TAG_KEY, tag, value)
+  }
+
+  /// Create a new object representing a tagged value, storing the tag under the given key 
+  /// instead of ```TAG_KEY```.
+  pub fn tagged_with(tag_key:&str, tag:&str, value:Data) -> DataObject {
+    let mut o = DataObject::new();
+    o.put_string(tag_key, tag);
+    o.set_property(TAG_VALUE_KEY, value);
+    o
+  }
+
+  /// Returns the tag and value if this object is a tagged value created by ```tagged()```.
+  pub fn as_tagged(&self) -> Option<(String, Data)> {
+    self.as_tagged_with(TAG_KEY)
+  }
+
+  /// Returns the tag and value if this object is a tagged value created by ```tagged_with()``` 
+  /// using the given tag key.
+  pub fn as_tagged_with(&self, tag_key:&str) -> Option<(String, Data)> {
+    let heap = &mut oheap().lock();
+    let map = heap.get(self.data_ref);
+    match (map.get(tag_key), map.get(TAG_VALUE_KEY)) {
+      (Some(Data::DString(tag)), Some(value)) => Some((tag.to_owned(), value.clone())),
+      _ => None,
+    }
+  }
+
+  /// Returns the seconds and nanoseconds since the Unix epoch if this object is a timestamp
+  /// created by ```put_timestamp()``` with nanoseconds less than ```1_000_000_000```.
+  pub fn as_timestamp(&self) -> Option<(i64, u32)> {
+    let (tag, value) = self.as_tagged()?;
+    if tag != TIMESTAMP_TAG || !value.is_object() { return None; }
+    let o = value.object();
+    match (o.try_get_int("secs"), o.try_get_int("nanos")) {
+      (Ok(secs), Ok(nanos)) if (0..1_000_000_000).contains(&nanos) => Some((secs, nanos as u32)),
+      _ => None,
+    }
+  }
+
+  /// Returns the approximate number of bytes of heap memory used by this object and the values
+  /// it contains, as for ```Data::heap_size()```.
+  pub fn memory_usage(&self) -> usize {
+    Data::DObject(self.data_ref).heap_size()
+  }
+  
+  /// Returns the total number of nodes in this object. This object and each object or array 
+  /// nested within it count as one node, as does every other value, including byte buffers.
+  /// An object or array that is referenced more than once (including by itself) is only
+  /// counted once.
+  pub fn deep_len(&self) -> usize {
+    deep_len_of(Data::DObject(self.data_ref), &mut Vec::new(), &mut Vec::new())
+  }
+
+  /// Returns a hash of the content of this object, which is the same for any two objects that
+  /// are structurally equal. See ```Data::content_hash()```.
+  pub fn content_hash(&self) -> u64 {
+    Data::DObject(self.data_ref).content_hash()
+  }
+
+  /// Returns the number of keys in this object, or 0 if the object is no longer on the heap.
+  pub fn len(&self) -> usize {
+    let heap = &mut oheap().lock();
+    heap.try_get(self.data_ref).map_or(0, |map| map.len())
+  }
+  
+  /// Returns ```true``` if this object has no keys or is no longer on the heap.
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+
   /// Returns ```true``` if this object contains the given key.
   pub fn has(&self, key:&str) -> bool {
     let heap = &mut oheap().lock();
@@ -201,11 +613,46 @@ impl DataObject {
   
   /// Returns a list (```Vec<String>```) of the keys in this object.
   pub fn keys(self) -> Vec<String> {
-    let mut vec = Vec::<String>::new();
-    for (key, _val) in self.objects() {
-      vec.push(key)
+    match self.try_keys() {
+      Ok(vec) => vec,
+      Err(e) => panic!("{}", e),
+    }
+  }
+  
+  /// Returns the keys in this object, or ```NDataError::InvalidObjectRef``` if this object is 
+  /// no longer on the heap.
+  pub fn try_keys(&self) -> Result<Vec<String>, NDataError> {
+    let heap = &mut oheap().lock();
+    match heap.try_get(self.data_ref) {
+      Some(map) => Ok(map.keys().cloned().collect()),
+      None => Err(NDataError::InvalidObjectRef(self.data_ref)),
+    }
+  }
+  
+  /// Returns an ```Entry``` for the given key, for inspecting or inserting its value in place.
+  pub fn entry(&mut self, key:&str) -> Entry<'_> {
+    Entry { object: self, key: key.to_string() }
+  }
+  
+  /// Returns the keys in this object that start with the given prefix, or an empty list if this
+  /// object is no longer on the heap.
+  pub fn keys_with_prefix(&self, prefix:&str) -> Vec<String> {
+    let heap = &mut oheap().lock();
+    match heap.try_get(self.data_ref) {
+      Some(map) => map.keys().filter(|k| k.starts_with(prefix)).cloned().collect(),
+      None => Vec::new(),
+    }
+  }
+  
+  /// Returns the keys in this object that match the given pattern, where ```*``` matches any 
+  /// sequence of characters (including none) and every other character matches itself. Returns
+  /// an empty list if this object is no longer on the heap.
+  pub fn keys_matching(&self, glob:&str) -> Vec<String> {
+    let heap = &mut oheap().lock();
+    match heap.try_get(self.data_ref) {
+      Some(map) => map.keys().filter(|k| glob_match(glob, k)).cloned().collect(),
+      None => Vec::new(),
     }
-    vec
   }
   
   /// Returns the stored value for the given key.
@@ -257,6 +704,15 @@ impl DataObject {
     d.float()
   }
 
+  /// Returns the stored value for the given key as an ```f64```, or ```None``` if the key is 
+  /// missing or the value cannot be read as a number. Unlike ```get_float()```, string values 
+  /// are also accepted: surrounding whitespace is trimmed and the rest must be a number in 
+  /// Rust's locale-independent float syntax (e.g. ```"1.5"```, ```"-2"```, ```"3e8"```, 
+  /// ```"inf"```). Thousands separators and decimal commas are not accepted.
+  pub fn get_float_lenient(&self, key:&str) -> Option<f64> {
+    self.try_get_property(key).ok()?.to_float_lenient()
+  }
+
   /// Returns the stored value for the given key as a ```DataObject```.
   pub fn get_object(&self, key:&str) -> DataObject {
     self.get_property(key).object()
@@ -272,29 +728,260 @@ impl DataObject {
     self.get_property(key).bytes()
   }
   
+  /// Returns the value at the given dot-separated path, such as ```"a.b.0.c"```. Each segment
+  /// is a key when the value reached so far is an object and an index when it is an array. On
+  /// failure returns ```NDataError::Path``` naming the segment that could not be followed.
+  pub fn get_path(&self, path:&str) -> Result<Data, NDataError> {
+    let mut data = Data::DObject(self.data_ref);
+    let mut end = 0;
+    for seg in path.split('.') {
+      end += seg.len();
+      let next = match &data {
+        Data::DObject(i) => DataObject::get(*i).try_get_property(seg),
+        Data::DArray(i) => match seg.parse::<usize>() {
+          Ok(index) => DataArray::get(*i).try_get_property(index),
+          Err(_) => Err(NDataError::InvalidIndex(seg.to_string())),
+        },
+        d => Err(NDataError::WrongDataType { expected: "object or array", found: d.type_name() }),
+      };
+      match next {
+        Ok(d) => data = d,
+        Err(e) => return Err(NDataError::Path { path: path[..end].to_string(), error: Box::new(e) }),
+      }
+      end += 1;
+    }
+    Ok(data)
+  }
+  
+  /// Returns the stored value for the given key, or an error if the key is not present.
+  pub fn try_get_property(&self, key:&str) -> Result<Data, NDataError> {
+    let heap = &mut oheap().lock();
+    let map = heap.get(self.data_ref);
+    match map.get(key) {
+      Some(data) => Ok(data.clone()),
+      None => Err(NDataError::KeyNotFound(key.to_string())),
+    }
+  }
+  
+  /// Returns the stored value for the given key as a ```String```, or an error if it is not a string.
+  pub fn try_get_string(&self, key:&str) -> Result<String, NDataError> {
+    self.try_get_property(key)?.try_string()
+  }
+  
+  /// Returns the stored value for the given key as a ```bool```, or an error if it is not a boolean.
+  pub fn try_get_boolean(&self, key:&str) -> Result<bool, NDataError> {
+    self.try_get_property(key)?.try_boolean()
+  }
+
+  /// Returns the stored value for the given key as an ```i64```, or an error if it is not an int.
+  pub fn try_get_int(&self, key:&str) -> Result<i64, NDataError> {
+    self.try_get_property(key)?.try_int()
+  }
+
+  /// Returns the stored value for the given key as an ```f64```, or an error if it is not a float.
+  /// As with ```get_float```, an int value is converted to f64.
+  pub fn try_get_float(&self, key:&str) -> Result<f64, NDataError> {
+    let d = self.try_get_property(key)?;
+    if d.is_int() { return Ok(d.int() as f64); }
+    d.try_float()
+  }
+
+  /// Returns the stored value for the given key as a ```DataObject```, or an error if it is not an object.
+  pub fn try_get_object(&self, key:&str) -> Result<DataObject, NDataError> {
+    self.try_get_property(key)?.try_object()
+  }
+  
+  /// Returns the stored value for the given key as a ```DataArray```, or an error if it is not an array.
+  pub fn try_get_array(&self, key:&str) -> Result<DataArray, NDataError> {
+    self.try_get_property(key)?.try_array()
+  }
+  
+  /// Returns the stored value for the given key as a ```DataBytes```, or an error if it is not a byte buffer.
+  pub fn try_get_bytes(&self, key:&str) -> Result<DataBytes, NDataError> {
+    self.try_get_property(key)?.try_bytes()
+  }
+  
+  /// Returns the array stored for the given key as a ```Vec<i64>```, or an error if the value
+  /// is not an array or any element is not an int.
+  pub fn try_get_int_array(&self, key:&str) -> Result<Vec<i64>, NDataError> {
+    self.try_get_array_of(key, |d| d.try_int())
+  }
+  
+  /// Returns the array stored for the given key as a ```Vec<f64>```, or an error if the value
+  /// is not an array or any element is not a number. Int elements are converted to ```f64```.
+  pub fn try_get_float_array(&self, key:&str) -> Result<Vec<f64>, NDataError> {
+    self.try_get_array_of(key, |d| if let Data::DInt(i) = d { Ok(*i as f64) } else { d.try_float() })
+  }
+  
+  /// Returns the array stored for the given key as a ```Vec<String>```, or an error if the 
+  /// value is not an array or any element is not a string.
+  pub fn try_get_string_array(&self, key:&str) -> Result<Vec<String>, NDataError> {
+    self.try_get_array_of(key, |d| d.try_string())
+  }
+  
+  /// Returns the array stored for the given key as a ```Vec<DataObject>```, or an error if the
+  /// value is not an array or any element is not an object.
+  pub fn try_get_object_array(&self, key:&str) -> Result<Vec<DataObject>, NDataError> {
+    self.try_get_array_of(key, |d| d.try_object())
+  }
+  
+  fn try_get_array_of<T>(&self, key:&str, f:impl Fn(&Data) -> Result<T, NDataError>) -> Result<Vec<T>, NDataError> {
+    let a = self.try_get_array(key)?;
+    a.objects().iter().enumerate().map(|(index, d)| f(d).map_err(|e| match e {
+      NDataError::WrongDataType { expected, found } => NDataError::WrongElementType { index, expected, found },
+      e => e,
+    })).collect()
+  }
+  
+  /// Returns the timestamp stored for the given key by ```put_timestamp()``` as seconds and 
+  /// nanoseconds since the Unix epoch, or panics if the value is not a timestamp.
+  pub fn get_timestamp(&self, key:&str) -> (i64, u32) {
+    match self.get_object(key).as_timestamp() {
+      Some(ts) => ts,
+      None => panic!("Not a timestamp: {}", key),
+    }
+  }
+  
   /// Remove the value from the object for the given key.
   pub fn remove_property(&mut self, key:&str) {
-    let oheap = &mut oheap().lock();
-    let map = oheap.get(self.data_ref);
-    if let Some(old) = map.remove(key){
-      if let Data::DObject(i) = &old {
-        let _x = DataObject {
-          data_ref: *i,
-        };
+    {
+      let oheap = &mut oheap().lock();
+      let map = oheap.get(self.data_ref);
+      if let Some(old) = map.remove(key){
+        if let Data::DObject(i) = &old {
+          let _x = DataObject {
+            data_ref: *i,
+          };
+        }
+        else if let Data::DArray(i) = &old {
+          let _x = DataArray {
+            data_ref: *i,
+          };
+        }
+        else if let Data::DBytes(i) = &old {
+          let _x = DataBytes {
+            data_ref: *i,
+          };
+        }
       }
-      else if let Data::DArray(i) = &old {
-        let _x = DataArray {
-          data_ref: *i,
-        };
+      else { return; }
+    }
+    fire_change(self.data_ref, key, &Data::DNull);
+  }
+  
+  /// Remove the value for the given key from the object and return it, or ```None``` if there 
+  /// is no such key. As for ```DataArray::drain_range()```, the reference held by the object is 
+  /// handed to the caller, so a returned object, array or byte buffer stays on the heap until 
+  /// the returned value is dropped.
+  pub fn take(&mut self, key:&str) -> Option<OwnedData> {
+    let old = {
+      let oheap = &mut oheap().lock();
+      let map = oheap.get(self.data_ref);
+      map.remove(key)?
+    };
+    fire_change(self.data_ref, key, &Data::DNull);
+    Some(OwnedData::new(old))
+  }
+  
+  /// Remove every key from the object and return the number removed. Callbacks registered with
+  /// ```on_change()``` are called for each removed key, as for ```remove_property()```.
+  pub fn clear(&mut self) -> usize {
+    let removed = {
+      let oheap = &mut oheap().lock();
+      let map = oheap.get(self.data_ref);
+      map.drain().collect::<Vec<(String, Data)>>()
+    };
+    let n = removed.len();
+    let observed = OBSERVED.load(Ordering::Acquire);
+    for (key, old) in removed {
+      old.queue_decr();
+      if observed { fire_change(self.data_ref, &key, &Data::DNull); }
+    }
+    n
+  }
+  
+  /// Register a callback to be called whenever the value for the given key is set with 
+  /// ```set_property()``` (or any of the ```put_*``` functions) or removed with 
+  /// ```remove_property()```. The callback receives the key and the new value, or ```DNull``` 
+  /// if the key was removed.
+  ///
+  /// The other functions that change an existing object also call it for each key they set or 
+  /// remove: ```take()```, ```clear()```, ```rename_key()```, ```rename_keys()```, 
+  /// ```rename_keys_deep()```, ```checked_increment_int()```, ```merge_into()```, 
+  /// ```deep_copy_into()```, and the ```Entry``` functions returned by ```entry()``` when they 
+  /// insert or modify a value. Objects 
+  /// built by the JSON parser are filled in before they are returned, so no callback can be 
+  /// registered on them yet.
+  ///
+  /// Callbacks are called after the object heap has been unlocked, so they may read and modify
+  /// data, including this object. Registrations are local to the current process and are 
+  /// discarded when the object is removed from the heap.
+  pub fn on_change(&self, key:&str, cb:fn(&str, &Data)) {
+    observers().lock().push((self.data_ref, key.to_string(), cb));
+    OBSERVED.store(true, Ordering::Release);
+  }
+  
+  /// Move the value for the key ```from``` to the key ```to```, replacing any value already 
+  /// there. Returns ```false``` if there is no value for ```from```.
+  pub fn rename_key(&mut self, from:&str, to:&str) -> bool {
+    let val = {
+      let oheap = &mut oheap().lock();
+      let map = oheap.get(self.data_ref);
+      if from == to { return map.contains_key(from); }
+      let Some(val) = map.remove(from) else { return false; };
+      if let Some(old) = map.insert(to.to_string(), val.clone()) {
+        old.queue_decr();
       }
-      else if let Data::DBytes(i) = &old {
-        let _x = DataBytes {
-          data_ref: *i,
-        };
+      val
+    };
+    if OBSERVED.load(Ordering::Acquire) {
+      fire_change(self.data_ref, from, &Data::DNull);
+      fire_change(self.data_ref, to, &val);
+    }
+    true
+  }
+  
+  /// Rename every key in this object to the value returned by the given function. If several 
+  /// keys end up with the same name, the value of the key that sorts last (compared 
+  /// lexicographically by byte value, before renaming) is kept and the others are discarded.
+  pub fn rename_keys(&mut self, mut f: impl FnMut(&str) -> String) {
+    let mut names = HashMap::<String,String>::new();
+    for (key, _val) in self.objects() {
+      let name = f(&key);
+      names.insert(key, name);
+    }
+    names.retain(|key, name| key != name);
+    {
+      let oheap = &mut oheap().lock();
+      let map = oheap.get(self.data_ref);
+      let mut entries = core::mem::take(map).into_iter().collect::<Vec<(String, Data)>>();
+      entries.sort_by(|a, b| a.0.cmp(&b.0));
+      for (key, val) in entries {
+        let key = names.get(&key).cloned().unwrap_or(key);
+        if let Some(old) = map.insert(key, val) {
+          old.queue_decr();
+        }
+      }
+    }
+    if OBSERVED.load(Ordering::Acquire) {
+      for key in names.keys() {
+        if !self.has(key) { fire_change(self.data_ref, key, &Data::DNull); }
+      }
+      for name in names.values() {
+        if let Ok(val) = self.try_get_property(name) { fire_change(self.data_ref, name, &val); }
       }
     }
   }
   
+  /// Rename every key in this object and in any objects nested within it, including objects
+  /// inside arrays, to the value returned by the given function. Each nested object is only
+  /// renamed once, even if it is referenced more than once.
+  pub fn rename_keys_deep(&mut self, mut f: impl FnMut(&str) -> String) {
+    let mut objects = Vec::<usize>::new();
+    let mut arrays = Vec::<usize>::new();
+    rename_keys_in(Data::DObject(self.data_ref), &mut f, &mut objects, &mut arrays);
+  }
+  
   /// Set the given value for the given key.
   pub fn set_property(&mut self, key:&str, data:Data) {
     if let Data::DObject(i) = &data {
@@ -310,24 +997,82 @@ impl DataObject {
       bheap.incr(*i);
     }
     
-    let oheap = &mut oheap().lock();
-    let map = oheap.get(self.data_ref);
-    if let Some(old) = map.insert(key.to_string(),data){
-      if let Data::DObject(i) = &old {
-        let _x = DataObject {
-          data_ref: *i,
-        };
+    let changed = if OBSERVED.load(Ordering::Acquire) { Some(data.clone()) } else { None };
+    {
+      let oheap = &mut oheap().lock();
+      let map = oheap.get(self.data_ref);
+      if let Some(old) = map.insert(key.to_string(),data){
+        if let Data::DObject(i) = &old {
+          let _x = DataObject {
+            data_ref: *i,
+          };
+        }
+        else if let Data::DArray(i) = &old {
+          let _x = DataArray {
+            data_ref: *i,
+          };
+        }
+        else if let Data::DBytes(i) = &old {
+          let _x = DataBytes {
+            data_ref: *i,
+          };
+        }
       }
-      else if let Data::DArray(i) = &old {
-        let _x = DataArray {
-          data_ref: *i,
-        };
+    }
+    if let Some(data) = changed {
+      fire_change(self.data_ref, key, &data);
+    }
+  }
+  
+  /// Add ```by``` to the ```i64``` value stored for the given key and return the new value. 
+  /// Returns ```None```, leaving the value unchanged, if the key is missing, the value is not
+  /// a ```DInt```, or the result would overflow.
+  pub fn checked_increment_int(&mut self, key:&str, by:i64) -> Option<i64> {
+    let n = {
+      let oheap = &mut oheap().lock();
+      let map = oheap.get(self.data_ref);
+      let Some(Data::DInt(i)) = map.get_mut(key) else { return None; };
+      *i = i.checked_add(by)?;
+      *i
+    };
+    fire_change(self.data_ref, key, &Data::DInt(n));
+    Some(n)
+  }
+  
+  /// Move every key value pair from ```source``` into this object, replacing the values of any
+  /// keys already present.
+  ///
+  /// If ```source``` is the only reference to its object, the values are moved without 
+  /// adjusting their reference counts and the source object is left empty. Otherwise they are
+  /// copied as with ```set_property()```.
+  pub fn merge_into(&mut self, source:DataObject) {
+    if source.data_ref == self.data_ref { return; }
+    let moved = {
+      let oheap = &mut oheap().lock();
+      if oheap.count(source.data_ref) == 1 {
+        let map = core::mem::take(oheap.get(source.data_ref));
+        let target = oheap.get(self.data_ref);
+        let mut moved = Vec::new();
+        for (k,v) in map {
+          if let Some(old) = target.insert(k.clone(), v.clone()) {
+            old.queue_decr();
+          }
+          moved.push((k, v));
+        }
+        Some(moved)
       }
-      else if let Data::DBytes(i) = &old {
-        let _x = DataBytes {
-          data_ref: *i,
-        };
+      else { None }
+    };
+    if let Some(moved) = moved {
+      if OBSERVED.load(Ordering::Acquire) {
+        for (k, v) in moved {
+          fire_change(self.data_ref, &k, &v);
+        }
       }
+      return;
+    }
+    for (k,v) in source.objects() {
+      self.set_property(&k, v);
     }
   }
   
@@ -389,17 +1134,102 @@ impl DataObject {
     self.set_property(key, Data::DBytes(a.data_ref));
   }
   
+  /// Set the CRC-32 checksum of the given ```DataBytes``` as an ```i64``` value for the given key.
+  pub fn put_crc32(&mut self, key:&str, b:&DataBytes) {
+    self.put_int(key, b.crc32() as i64);
+  }
+  
+  /// Set a timestamp, given as seconds and nanoseconds since the Unix epoch, for the given key.
+  ///
+  /// The timestamp is stored as a tagged object (see ```tagged()```) with the tag 
+  /// ```TIMESTAMP_TAG```. Use ```get_timestamp()``` to read it back. ```nanos``` must be less 
+  /// than ```1_000_000_000```, or the value is not read back as a timestamp.
+  pub fn put_timestamp(&mut self, key:&str, secs:i64, nanos:u32) {
+    let mut ts = DataObject::new();
+    ts.put_int("secs", secs);
+    ts.put_int("nanos", nanos as i64);
+    let o = DataObject::tagged(TIMESTAMP_TAG, Data::DObject(ts.data_ref));
+    self.put_object(key, o);
+  }
+  
   /// Set the for the given key to ```DNull```.
   pub fn put_null(&mut self, key:&str) {
     self.set_property(key, Data::DNull);
   }
+
+  /// Set the given value for the given key and return this object, for chaining.
+  ///
+  /// ```
+  /// # use ndata::dataobject::DataObject;
+  /// # ndata::init();
+  /// let o = DataObject::new().with_int("a", 1).with_string("b", "x");
+  /// ```
+  pub fn with_property(mut self, key:&str, data:Data) -> DataObject {
+    self.set_property(key, data);
+    self
+  }
+
+  /// Set the given ```String``` value for the given key and return this object, for chaining.
+  pub fn with_string(mut self, key:&str, val:&str) -> DataObject {
+    self.put_string(key, val);
+    self
+  }
+
+  /// Set the given ```bool``` value for the given key and return this object, for chaining.
+  pub fn with_boolean(mut self, key:&str, val:bool) -> DataObject {
+    self.put_boolean(key, val);
+    self
+  }
+
+  /// Set the given ```i64``` value for the given key and return this object, for chaining.
+  pub fn with_int(mut self, key:&str, val:i64) -> DataObject {
+    self.put_int(key, val);
+    self
+  }
+
+  /// Set the given ```f64``` value for the given key and return this object, for chaining.
+  pub fn with_float(mut self, key:&str, val:f64) -> DataObject {
+    self.put_float(key, val);
+    self
+  }
+
+  /// Set the given ```DataObject``` value for the given key and return this object, for chaining.
+  pub fn with_object(mut self, key:&str, o:DataObject) -> DataObject {
+    self.put_object(key, o);
+    self
+  }
+
+  /// Set the given ```DataArray``` value for the given key and return this object, for chaining.
+  pub fn with_array(mut self, key:&str, a:DataArray) -> DataObject {
+    self.put_array(key, a);
+    self
+  }
+
+  /// Set the given ```DataBytes``` value for the given key and return this object, for chaining.
+  pub fn with_bytes(mut self, key:&str, b:DataBytes) -> DataObject {
+    self.put_bytes(key, b);
+    self
+  }
+
+  /// Set the given key to ```DNull``` and return this object, for chaining.
+  pub fn with_null(mut self, key:&str) -> DataObject {
+    self.put_null(key);
+    self
+  }
+
+  /// Forget any callbacks registered with ```on_change()``` for the given object.
+  pub(crate) fn remove_observers(data_ref:usize) {
+    if OBSERVED.load(Ordering::Acquire) {
+      observers().lock().retain(|(i, _k, _cb)| *i != data_ref);
+    }
+  }
   
   /// **DO NOT USE**
   ///
   /// Reduces the reference count for this object by one, as well as the reference counts of any
   /// objects, arrays, or byte buffers contained in this object. This function should only be used
   /// externally by ```DataArray::gc()```.
-  pub fn delete(oheap:&mut Heap<HashMap<String,Data>>, data_ref:usize, aheap:&mut Heap<Vec<Data>>) {
+  pub fn delete(oheap:&mut Heap<HashMap<String,Data>>, data_ref:usize, aheap:&mut Heap<ArrayValues>) {
     let mut objects_to_kill = Vec::<usize>::new();
     let mut arrays_to_kill = Vec::<usize>::new();
     
@@ -419,6 +1249,7 @@ impl DataObject {
           };
         }
       }
+      DataObject::remove_observers(data_ref);
     }
     oheap.decr(data_ref);
     
@@ -432,41 +1263,357 @@ impl DataObject {
   
   /// Returns the key value pairs in this object as a ```Vec<String, Data>```. 
   pub fn objects(&self) -> Vec<(String, Data)> {
+    match self.try_objects() {
+      Ok(vec) => vec,
+      Err(e) => panic!("{}", e),
+    }
+  }
+  
+  /// Returns the key value pairs in this object, or ```NDataError::InvalidObjectRef``` if this 
+  /// object is no longer on the heap.
+  pub fn try_objects(&self) -> Result<Vec<(String, Data)>, NDataError> {
     let heap = &mut oheap().lock();
-    let map = heap.get(self.data_ref);
+    let Some(map) = heap.try_get(self.data_ref) else {
+      return Err(NDataError::InvalidObjectRef(self.data_ref));
+    };
     let mut vec = Vec::<(String, Data)>::new();
     for (k,v) in map {
       vec.push((k.to_string(),v.clone()));
     }
-    vec
+    Ok(vec)
+  }
+  
+  /// Returns an iterator over the key value pairs in this object. The pairs are copied when 
+  /// this is called, so later changes to the object are not seen by the iterator. The iterator 
+  /// holds a reference to this object until it is dropped.
+  pub fn iter(&self) -> DataObjectIter {
+    DataObjectIter {
+      _obj: self.clone(),
+      iter: self.objects().into_iter(),
+    }
   }
   
   /// Prints the objects currently stored in the heap
   #[cfg(not(feature="no_std_support"))]
   pub fn print_heap() {
-    println!("object {:?}", &mut oheap().lock().keys());
+    let heap = &oheap().lock();
+    println!("object {:?} fill {:.2}", heap.keys(), heap.fill_ratio());
+  }
+  
+  /// Prints the objects currently stored in the heap to stderr, unless the heap is locked.
+  #[cfg(not(feature="no_std_support"))]
+  pub(crate) fn print_heap_at_panic() {
+    match oheap().try_lock_if_initialized() {
+      Some(heap) => eprintln!("object {:?} fill {:.2}", heap.keys(), heap.fill_ratio()),
+      None => eprintln!("object <locked>"),
+    }
   }
   
   /// Perform garbage collection. Objects will not be removed from the heap until
   /// ```DataObject::gc()``` is called.
+  ///
+  /// Does nothing if called while this thread is already performing garbage collection.
   pub fn gc() {
+    let Some(_gc) = GcGuard::enter() else { return; };
     let oheap = &mut oheap().lock();
     let aheap = &mut aheap().lock();
-    let odrop = &mut odrop().lock();
-    let mut i = odrop.len();
-    while i>0 {
-      i = i - 1;
-      let x = odrop.remove(0);
+    // Release the drop queue before deleting so that handles dropped meanwhile can be queued
+    let odrop = core::mem::take(&mut *odrop().lock());
+    for x in odrop {
       DataObject::delete(oheap, x, aheap);
     }
   }
+  
+  /// Perform garbage collection as for ```gc()```, unless a lock it needs is already held.
+  pub(crate) fn try_gc() {
+    let Some(_gc) = GcGuard::enter() else { return; };
+    let Some(mut oheap) = oheap().try_lock_if_initialized() else { return; };
+    let Some(mut aheap) = aheap().try_lock_if_initialized() else { return; };
+    let Some(mut queue) = odrop().try_lock_if_initialized() else { return; };
+    let odrop = core::mem::take(&mut *queue);
+    drop(queue);
+    for x in odrop {
+      DataObject::delete(&mut oheap, x, &mut aheap);
+    }
+  }
+}
+
+/// A key in a ```DataObject```, returned by ```DataObject::entry()```.
+///
+/// Whether the key is present is checked by each operation, under the same lock as any insert 
+/// or change, so an entry remains correct if another thread changes the object in the meantime.
+pub struct Entry<'a> {
+  object: &'a mut DataObject,
+  key: String,
+}
+
+impl Entry<'_> {
+  /// Returns the key of this entry.
+  pub fn key(&self) -> &str {
+    &self.key
+  }
+  
+  fn into_parts(self) -> (usize, String) {
+    (self.object.data_ref, self.key)
+  }
+  
+  /// Returns the object stored for the key, first inserting a new (empty) object if the key is 
+  /// absent. Panics if the stored value is not an object.
+  pub fn or_insert_object(self) -> DataObject {
+    let (data_ref, key) = self.into_parts();
+    let (i, inserted) = {
+      let oheap = &mut oheap().lock();
+      let (i, inserted) = match oheap.get(data_ref).get(&key) {
+        Some(Data::DObject(i)) => (*i, false),
+        Some(d) => panic!("Not an object: {:?}", d),
+        None => {
+          let i = oheap.push(HashMap::new());
+          oheap.get(data_ref).insert(key.clone(), Data::DObject(i));
+          (i, true)
+        },
+      };
+      oheap.incr(i);
+      (i, inserted)
+    };
+    if inserted { fire_change(data_ref, &key, &Data::DObject(i)); }
+    DataObject { data_ref: i }
+  }
+  
+  /// Returns the array stored for the key, first inserting a new (empty) array if the key is 
+  /// absent. Panics if the stored value is not an array.
+  pub fn or_insert_array(self) -> DataArray {
+    let (data_ref, key) = self.into_parts();
+    let (i, inserted) = {
+      let oheap = &mut oheap().lock();
+      let aheap = &mut aheap().lock();
+      let map = oheap.get(data_ref);
+      let (i, inserted) = match map.get(&key) {
+        Some(Data::DArray(i)) => (*i, false),
+        Some(d) => panic!("Not an array: {:?}", d),
+        None => {
+          let i = aheap.push(ArrayValues::default());
+          map.insert(key.clone(), Data::DArray(i));
+          (i, true)
+        },
+      };
+      aheap.incr(i);
+      (i, inserted)
+    };
+    if inserted { fire_change(data_ref, &key, &Data::DArray(i)); }
+    DataArray { data_ref: i }
+  }
+  
+  /// Returns the value stored for the key, first inserting the value returned by ```f``` if the
+  /// key is absent. ```f``` is called without holding any lock, so it may create data. If 
+  /// another thread inserts the key while ```f``` runs, that value is kept instead.
+  pub fn or_insert_with(self, f: impl FnOnce() -> Data) -> Data {
+    let (data_ref, key) = self.into_parts();
+    if let Some(d) = oheap().lock().get(data_ref).get(&key) { return d.clone(); }
+    let d = f();
+    if let Data::DArray(i) = &d { aheap().lock().incr(*i); }
+    else if let Data::DBytes(i) = &d { bheap().lock().incr(*i); }
+    {
+      let oheap = &mut oheap().lock();
+      if let Data::DObject(i) = &d { oheap.incr(*i); }
+      let map = oheap.get(data_ref);
+      if let Some(old) = map.get(&key) {
+        d.queue_decr();
+        return old.clone();
+      }
+      map.insert(key.clone(), d.clone());
+    }
+    fire_change(data_ref, &key, &d);
+    d
+  }
+  
+  /// Call ```f``` with the value stored for the key, if present, to modify it in place. 
+  ///
+  /// The object is locked while ```f``` is called, so that no other thread can change the value
+  /// in the meantime, and ```f``` must not access any ```DataObject```.
+  pub fn and_modify(self, f: impl FnOnce(&mut Data)) -> Self {
+    let data_ref = self.object.data_ref;
+    let changed = {
+      let oheap = &mut oheap().lock();
+      match oheap.get(data_ref).get_mut(&self.key) {
+        Some(d) => {
+          let old = d.clone();
+          f(d);
+          let new = d.clone();
+          if let Data::DObject(i) = &new { oheap.incr(*i); }
+          else if let Data::DArray(i) = &new { aheap().lock().incr(*i); }
+          else if let Data::DBytes(i) = &new { bheap().lock().incr(*i); }
+          Some((old, new))
+        },
+        None => None,
+      }
+    };
+    if let Some((old, new)) = changed {
+      old.queue_decr();
+      fire_change(data_ref, &self.key, &new);
+    }
+    self
+  }
+}
+
+/// Count the nodes in the given value that have not already been visited. See 
+/// ```DataObject::deep_len()```.
+pub(crate) fn deep_len_of(d:Data, objects:&mut Vec<usize>, arrays:&mut Vec<usize>) -> usize {
+  let values = match d {
+    Data::DObject(i) => {
+      if objects.contains(&i) { return 0; }
+      objects.push(i);
+      DataObject::get(i).objects().into_iter().map(|(_k, v)| v).collect()
+    },
+    Data::DArray(i) => {
+      if arrays.contains(&i) { return 0; }
+      arrays.push(i);
+      DataArray::get(i).objects()
+    },
+    _ => return 1,
+  };
+  let mut n = 1;
+  for v in values {
+    n += deep_len_of(v, objects, arrays);
+  }
+  n
+}
+
+/// Rename the keys of the given object, or the objects within the given array, and of all 
+/// objects nested within them that have not already been visited
+fn rename_keys_in(d:Data, f:&mut impl FnMut(&str) -> String, objects:&mut Vec<usize>, arrays:&mut Vec<usize>) {
+  let values = match d {
+    Data::DObject(i) => {
+      if objects.contains(&i) { return; }
+      objects.push(i);
+      let mut o = DataObject::get(i);
+      o.rename_keys(&mut *f);
+      o.objects().into_iter().map(|(_k, v)| v).collect()
+    },
+    Data::DArray(i) => {
+      if arrays.contains(&i) { return; }
+      arrays.push(i);
+      DataArray::get(i).objects()
+    },
+    _ => return,
+  };
+  for v in values {
+    rename_keys_in(v, f, objects, arrays);
+  }
+}
+
+/// Returns ```true``` if the string matches the pattern, where ```*``` matches any sequence of 
+/// characters
+fn glob_match(glob:&str, s:&str) -> bool {
+  let p: Vec<char> = glob.chars().collect();
+  let t: Vec<char> = s.chars().collect();
+  let mut i = 0;
+  let mut j = 0;
+  let mut star: Option<(usize, usize)> = None;
+  while j < t.len() {
+    if i < p.len() && p[i] == '*' { star = Some((i, j)); i += 1; }
+    else if i < p.len() && p[i] == t[j] { i += 1; j += 1; }
+    else if let Some((si, sj)) = star { i = si + 1; j = sj + 1; star = Some((si, sj + 1)); }
+    else { return false; }
+  }
+  while i < p.len() && p[i] == '*' { i += 1; }
+  i == p.len()
+}
+
+/// Percent-encode all but the unreserved characters of the string
+fn percent_encode(s:&str) -> String {
+  let mut out = String::new();
+  for b in s.bytes() {
+    if b.is_ascii_alphanumeric() || b == b'-' || b == b'_' || b == b'.' || b == b'~' { out.push(b as char); }
+    else { out += &format!("%{:02X}", b); }
+  }
+  out
+}
+
+/// Decode a percent-encoded string, treating ```+``` as a space
+fn percent_decode(s:&str) -> String {
+  let ba = s.as_bytes();
+  let mut out = Vec::<u8>::new();
+  let mut i = 0;
+  while i < ba.len() {
+    let b = ba[i];
+    if b == b'+' { out.push(b' '); }
+    else if b == b'%' && i + 2 < ba.len() {
+      match ((ba[i+1] as char).to_digit(16), (ba[i+2] as char).to_digit(16)) {
+        (Some(hi), Some(lo)) => { out.push((hi * 16 + lo) as u8); i += 2; }
+        _ => out.push(b),
+      }
+    }
+    else { out.push(b); }
+    i += 1;
+  }
+  String::from_utf8_lossy(&out).to_string()
+}
+
+/// An iterator over the key value pairs of a ```DataObject```, returned by ```DataObject::iter()```.
+pub struct DataObjectIter {
+  _obj: DataObject,
+  iter: std::vec::IntoIter<(String, Data)>,
+}
+
+impl Iterator for DataObjectIter {
+  type Item = (String, Data);
+  
+  fn next(&mut self) -> Option<Self::Item> {
+    self.iter.next()
+  }
+  
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    self.iter.size_hint()
+  }
+}
+
+impl IntoIterator for DataObject {
+  type Item = (String, Data);
+  type IntoIter = DataObjectIter;
+  
+  fn into_iter(self) -> DataObjectIter {
+    let iter = self.objects().into_iter();
+    DataObjectIter { _obj: self, iter }
+  }
+}
+
+impl IntoIterator for &DataObject {
+  type Item = (String, Data);
+  type IntoIter = DataObjectIter;
+  
+  fn into_iter(self) -> DataObjectIter {
+    self.iter()
+  }
+}
+
+/// Serializes the object as a map, as returned by ```to_json()```.
+#[cfg(feature="serde_support")]
+impl serde::Serialize for DataObject {
+  fn serialize<S: serde::Serializer>(&self, serializer:S) -> Result<S::Ok, S::Error> {
+    self.to_json().serialize(serializer)
+  }
+}
+
+/// Deserializes a map into a new object, as with ```from_json()```.
+#[cfg(feature="serde_support")]
+impl<'de> serde::Deserialize<'de> for DataObject {
+  fn deserialize<D: serde::Deserializer<'de>>(deserializer:D) -> Result<DataObject, D::Error> {
+    let value = Value::deserialize(deserializer)?;
+    if !value.is_object() { return Err(serde::de::Error::custom("expected a map for DataObject")); }
+    Ok(DataObject::from_json(value))
+  }
 }
 
 /// Adds this ```DataObject```'s data_ref to ADROP. Reference counts are adjusted when
-/// ```DataObject::gc()``` is called.
+/// ```DataObject::gc()``` is called, or here if the GC threshold is exceeded.
 impl Drop for DataObject {
   fn drop(&mut self) {
-    let _x = &mut odrop().lock().push(self.data_ref);
+    let queued = {
+      let odrop = &mut odrop().lock();
+      odrop.push(self.data_ref);
+      odrop.len()
+    };
+    if crate::over_gc_threshold(queued) { crate::try_gc(); }
   }
 }
 
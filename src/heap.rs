@@ -4,6 +4,42 @@ use crate::usizemap::*;
 #[cfg(feature="no_std_support")]
 use alloc::vec::Vec;
 
+#[cfg(not(feature="no_std_support"))]
+std::thread_local! {
+  /// Set while the current thread is performing garbage collection
+  static IN_GC: core::cell::Cell<bool> = const { core::cell::Cell::new(false) };
+}
+
+/// Set while any thread is performing garbage collection (no thread locals without std)
+#[cfg(feature="no_std_support")]
+static IN_GC: core::sync::atomic::AtomicBool = core::sync::atomic::AtomicBool::new(false);
+
+/// Marks the current thread as performing garbage collection until dropped
+pub(crate) struct GcGuard;
+
+impl GcGuard {
+  /// Mark the current thread as performing garbage collection, or return ```None``` if it 
+  /// already is, in which case the caller must not start another collection. When the 
+  /// "no_std_support" feature is enabled this also returns ```None``` while another thread is
+  /// performing garbage collection.
+  pub(crate) fn enter() -> Option<GcGuard> {
+    #[cfg(not(feature="no_std_support"))]
+    if IN_GC.with(|x| x.replace(true)) { return None; }
+    #[cfg(feature="no_std_support")]
+    if IN_GC.swap(true, core::sync::atomic::Ordering::Acquire) { return None; }
+    Some(GcGuard)
+  }
+}
+
+impl Drop for GcGuard {
+  fn drop(&mut self) {
+    #[cfg(not(feature="no_std_support"))]
+    IN_GC.with(|x| x.set(false));
+    #[cfg(feature="no_std_support")]
+    IN_GC.store(false, core::sync::atomic::Ordering::Release);
+  }
+}
+
 #[derive(Debug)]
 struct Blob<T> {
   data: T,
@@ -24,6 +60,29 @@ impl<T: core::fmt::Debug> Heap<T> {
     }
   }
 
+  /// Create a new ```Heap``` of type ```T``` with space pre-allocated for at least ```capacity``` 
+  /// instances.
+  pub fn with_capacity(capacity:usize) -> Heap<T> {
+    Heap {
+      data: UsizeMap::<Blob<T>>::with_capacity(capacity),
+    }
+  }
+
+  /// Create a new ```Heap``` of type ```T``` that stores its instances in the given region of 
+  /// memory instead of allocating space for them, as for ```UsizeMap::in_region()```. Use 
+  /// ```region_size()``` to find how large the region must be. Pushing to a full heap panics.
+  pub fn in_region(region:&'static mut [core::mem::MaybeUninit<u8>]) -> Heap<T> {
+    Heap {
+      data: UsizeMap::<Blob<T>>::in_region(region),
+    }
+  }
+
+  /// Return the number of bytes a region passed to ```in_region()``` needs in order to hold 
+  /// ```capacity``` instances.
+  pub const fn region_size(capacity:usize) -> usize {
+    UsizeMap::<Blob<T>>::region_size(capacity)
+  }
+
   /// Push an instance of type ```T``` to the heap and return a (```usize```) reference to it.
   pub fn push(&mut self, data: T) -> usize {
     let blob = Blob{
@@ -46,6 +105,20 @@ impl<T: core::fmt::Debug> Heap<T> {
     Some(&mut x.unwrap().data)
   }
 
+  /// Call the given function with the reference and value of every instance on the heap.
+  pub fn for_each_mut(&mut self, mut f: impl FnMut(usize, &mut T)) {
+    for (i, blob) in self.data.iter_mut() {
+      f(i, &mut blob.data);
+    }
+  }
+
+  /// Return the values for the given data references, or ```None``` if any reference is
+  /// missing or repeated.
+  pub fn get_disjoint_mut<const N: usize>(&mut self, indices:[usize; N]) -> Option<[&mut T; N]> {
+    let blobs = self.data.get_disjoint_mut(indices)?;
+    Some(blobs.map(|blob| &mut blob.data))
+  }
+
   /// Return the given instance's reference count.
   pub fn count(&mut self, index:usize) -> usize {
     self.data[index].count
@@ -68,6 +141,37 @@ impl<T: core::fmt::Debug> Heap<T> {
     }
   }
   
+  /// Remove the given instance from the heap regardless of its reference count, and return it.
+  /// References held by the removed instance are not released.
+  pub fn remove(&mut self, index:usize) -> Option<T> {
+    self.data.remove(index).map(|blob| blob.data)
+  }
+  
+  /// Remove any instances whose reference count is zero and return how many were removed. Such
+  /// instances can only result from unbalanced calls to ```decr()```. References held by the 
+  /// removed instances are not released.
+  pub fn sweep_zero(&mut self) -> usize {
+    let mut zero = Vec::new();
+    for (i, blob) in self.data.iter_mut() {
+      if blob.count == 0 { zero.push(i); }
+    }
+    for i in &zero {
+      self.data.remove(*i);
+    }
+    zero.len()
+  }
+  
+  /// Release the unused slots at the end of the heap and any excess memory, returning the number
+  /// of slots released. Data references in use remain valid.
+  pub fn shrink(&mut self) -> usize {
+    self.data.shrink_to_fit()
+  }
+  
+  /// Return the ratio of live instances to allocated slots on the heap, from ```0.0``` to ```1.0```.
+  pub fn fill_ratio(&self) -> f64 {
+    self.data.fill_ratio()
+  }
+
   /// List the keys to the data on the heap
   pub fn keys(&self) -> Vec<usize> {
     self.data.keys()
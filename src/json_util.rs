@@ -1,207 +1,714 @@
 extern crate alloc;
+use core::fmt;
+use core::sync::atomic::AtomicBool;
+use core::sync::atomic::AtomicU8;
+use core::sync::atomic::Ordering;
 use crate::data::*;
 use crate::dataarray::*;
 use crate::dataobject::*;
+#[cfg(feature="bytes_json")]
+use crate::databytes::*;
 
 #[cfg(feature="no_std_support")]
 use alloc::string::ToString;
 #[cfg(feature="no_std_support")]
 use alloc::string::String;
 
+/// Whether ```escape()``` also escapes forward slashes
+static ESCAPE_SLASH:AtomicBool = AtomicBool::new(false);
+
+/// Set whether forward slashes are escaped as ```\/``` when serializing. Defaults to ```false```.
+///
+/// Enabling this makes the output safe to embed inside an HTML ```<script>``` tag.
+pub fn set_escape_slash(b:bool) {
+  ESCAPE_SLASH.store(b, Ordering::Relaxed);
+}
+
+/// Returns ```true``` if forward slashes are escaped when serializing.
+pub fn is_escape_slash() -> bool {
+  ESCAPE_SLASH.load(Ordering::Relaxed)
+}
+
+pub use crate::dataobject::set_timestamps_as_rfc3339;
+pub use crate::dataobject::is_timestamps_as_rfc3339;
+pub use crate::dataobject::rfc3339;
+pub use crate::ndataerror::ParseError;
+pub use crate::ndataerror::ParseErrorAt;
+
 /// Create a JSON string from a DataObject.
 pub fn object_to_string(o:DataObject) -> String {
-  let mut s = "{".to_string();
-  let mut i = 0;
-  for key in o.clone().keys(){
-    if i>0 { s += "," }
-    s += "\"";
-    s += &escape(&key);
-    s += "\":";
-    let p = o.get_property(&key);
-    if p.is_string() || p.is_bytes(){
-      s += "\"";
-      s += &escape(&Data::as_string(p));
-      s += "\"";
-    }
-    else if p.is_object() {
-      s += &object_to_string(p.object());
-    }
-    else if p.is_array() {
-      s += &array_to_string(p.array());
-    }
-    else { s += &Data::as_string(p); }
-    i += 1;
-  }
-  s += "}";
+  let mut s = String::new();
+  write_object_styled(&mut s, &o, Style::COMPACT, 0).unwrap();
   s
 }
 
 /// Create a JSON string from a DataArray.
 pub fn array_to_string(o:DataArray) -> String {
-  let mut s = "[".to_string();
-  let mut i = 0;
-  for p in o.clone().objects(){
-    if i>0 { s += "," }
-    if p.is_string() {
-      s += "\"";
-      s += &escape(&p.string());
-      s += "\"";
+  let mut s = String::new();
+  write_array_styled(&mut s, &o, Style::COMPACT, 0).unwrap();
+  s
+}
+
+/// How floating point numbers that are not finite are handled when serializing, since JSON has 
+/// no representation for ```NaN``` or infinity
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub enum NonFinitePolicy {
+  /// Write the value as ```null```
+  #[default]
+  Null,
+  /// Fail with ```fmt::Error```
+  Error,
+}
+
+impl NonFinitePolicy {
+  fn from_u8(b:u8) -> NonFinitePolicy {
+    match b {
+      1 => NonFinitePolicy::Error,
+      _ => NonFinitePolicy::Null,
     }
-    else if p.is_object() {
-      s += &object_to_string(p.object());
+  }
+}
+
+/// The ```NonFinitePolicy``` used when serializing
+static NON_FINITE_POLICY:AtomicU8 = AtomicU8::new(0);
+
+/// Set how floats that are ```NaN``` or infinite are handled by the ```write_*``` functions. 
+/// Defaults to ```NonFinitePolicy::Null```.
+///
+/// With ```NonFinitePolicy::Error``` the ```write_*``` functions return an error when such a 
+/// value is encountered. The ```*_to_string*``` functions, and ```to_string()``` on a 
+/// ```DataObject``` or ```DataArray```, cannot fail and always write ```null```.
+pub fn set_non_finite_policy(policy:NonFinitePolicy) {
+  NON_FINITE_POLICY.store(policy as u8, Ordering::Relaxed);
+}
+
+/// Returns how floats that are ```NaN``` or infinite are handled by the ```write_*``` functions.
+pub fn non_finite_policy() -> NonFinitePolicy {
+  NonFinitePolicy::from_u8(NON_FINITE_POLICY.load(Ordering::Relaxed))
+}
+
+/// How floating point numbers are written as JSON. Values that are not finite are written as 
+/// ```null```, or handled according to ```non_finite_policy()``` by the ```write_*``` functions.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub enum FloatFormat {
+  /// The shortest representation that parses back to the same value, such as ```0.1```
+  #[default]
+  Shortest,
+  /// The given number of digits after the decimal point, such as ```0.100``` for ```Fixed(3)```
+  Fixed(usize),
+  /// Scientific notation, such as ```1e-1```
+  Scientific,
+}
+
+impl FloatFormat {
+  fn write<W: fmt::Write>(&self, w:&mut W, f:f64, policy:NonFinitePolicy) -> fmt::Result {
+    if !f.is_finite() {
+      return match policy {
+        NonFinitePolicy::Null => w.write_str("null"),
+        NonFinitePolicy::Error => Err(fmt::Error),
+      };
     }
-    else if p.is_array() {
-      s += &array_to_string(p.array());
+    match self {
+      FloatFormat::Shortest => write!(w, "{}", f),
+      FloatFormat::Fixed(precision) => write!(w, "{:.*}", precision, f),
+      FloatFormat::Scientific => write!(w, "{:e}", f),
     }
-    else { s += &Data::as_string(p); }
-    i += 1;
   }
-  s += "]";
+}
+
+/// Create a JSON string from a DataObject, writing floating point numbers in the given format.
+pub fn object_to_string_with_float_format(o:DataObject, format:FloatFormat) -> String {
+  let mut s = String::new();
+  write_object_styled(&mut s, &o, Style { float_format: format, ..Style::COMPACT }, 0).unwrap();
   s
 }
 
-/// Create a new DataObject from a JSON string.
-pub fn object_from_string(s:&str) -> DataObject {
-  let s = s.trim();
-  let (o, n) = extract_object(s);
-  if n<s.len() { panic!("Error parsing DataObject, extra characters: '{}'", &s[n..]); }
-  o
+/// Create a JSON string from a DataArray, writing floating point numbers in the given format.
+pub fn array_to_string_with_float_format(o:DataArray, format:FloatFormat) -> String {
+  let mut s = String::new();
+  write_array_styled(&mut s, &o, Style { float_format: format, ..Style::COMPACT }, 0).unwrap();
+  s
+}
+
+/// Create a JSON string from a DataObject with the keys of each object written in sorted order, 
+/// so that objects with the same contents always produce the same string.
+pub fn object_to_string_sorted(o:DataObject) -> String {
+  let mut s = String::new();
+  write_object_styled(&mut s, &o, Style { sort_keys: true, ..Style::COMPACT }, 0).unwrap();
+  s
+}
+
+/// Create a JSON string from a DataArray with the keys of each object written in sorted order.
+pub fn array_to_string_sorted(o:DataArray) -> String {
+  let mut s = String::new();
+  write_array_styled(&mut s, &o, Style { sort_keys: true, ..Style::COMPACT }, 0).unwrap();
+  s
+}
+
+/// Create an indented JSON string from a DataObject, with ```indent``` spaces per level of 
+/// nesting. If ```sort_keys``` is ```true``` the keys of each object are written in sorted order.
+pub fn object_to_string_pretty(o:DataObject, indent:usize, sort_keys:bool) -> String {
+  let mut s = String::new();
+  write_object_styled(&mut s, &o, Style { indent: Some(indent), sort_keys, ..Style::COMPACT }, 0).unwrap();
+  s
 }
 
-fn extract_object(s:&str) -> (DataObject, usize) {
-  let mut o = DataObject::new();
-  let s = s.trim();
-  if !s.starts_with("{") { panic!("Error parsing DataObject from: {}", s); }
+/// Create an indented JSON string from a DataArray, with ```indent``` spaces per level of 
+/// nesting. If ```sort_keys``` is ```true``` the keys of each object are written in sorted order.
+pub fn array_to_string_pretty(o:DataArray, indent:usize, sort_keys:bool) -> String {
+  let mut s = String::new();
+  write_array_styled(&mut s, &o, Style { indent: Some(indent), sort_keys, ..Style::COMPACT }, 0).unwrap();
+  s
+}
+
+/// Write a DataObject as JSON to the given writer. Floats that are not finite are handled 
+/// according to ```non_finite_policy()```.
+pub fn write_object<W: fmt::Write>(w:&mut W, o:&DataObject) -> fmt::Result {
+  write_object_styled(w, o, Style::writing(), 0)
+}
+
+/// Write a DataArray as JSON to the given writer. Floats that are not finite are handled 
+/// according to ```non_finite_policy()```.
+pub fn write_array<W: fmt::Write>(w:&mut W, o:&DataArray) -> fmt::Result {
+  write_array_styled(w, o, Style::writing(), 0)
+}
+
+/// Write a value as JSON to the given writer. Byte buffers are written as hexidecimal strings.
+/// Floats that are not finite are handled according to ```non_finite_policy()```.
+pub fn write_data<W: fmt::Write>(w:&mut W, p:Data) -> fmt::Result {
+  write_data_styled(w, p, Style::writing(), 0)
+}
+
+/// Layout of the JSON written by the ```write_*_styled``` functions
+#[derive(Copy, Clone)]
+struct Style {
+  /// Spaces per level of nesting, or ```None``` to write everything on one line
+  indent: Option<usize>,
+  /// Whether the keys of each object are written in sorted order
+  sort_keys: bool,
+  /// How floating point numbers are written
+  float_format: FloatFormat,
+  /// How floating point numbers that are not finite are handled
+  non_finite: NonFinitePolicy,
+}
+
+impl Style {
+  const COMPACT:Style = Style { indent: None, sort_keys: false, float_format: FloatFormat::Shortest, non_finite: NonFinitePolicy::Null };
   
-  let nn = s.len();
-  let mut s = s[1..].trim();
+  /// The compact style, handling floats that are not finite according to 
+  /// ```non_finite_policy()```
+  fn writing() -> Style {
+    Style { non_finite: non_finite_policy(), ..Style::COMPACT }
+  }
   
-  loop {
-    if s.starts_with("}") { break; }
-    let key = extract_string(s, '\"', '\"');
-    if key.is_none() {
-      if s != "" { panic!("Error parsing DataObject, unexpected characters: {}", s); }
-      break;
+  /// Start a new line at the given depth, if indenting.
+  fn newline<W: fmt::Write>(&self, w:&mut W, depth:usize) -> fmt::Result {
+    if let Some(indent) = self.indent {
+      w.write_str("\n")?;
+      for _ in 0..indent * depth { w.write_str(" ")?; }
     }
-    let key = key.unwrap();
-    let n = key.len();
-    s = &s[n..].trim();
-    let key = &key[1..n-1];
-
-    if !s.starts_with(":") { panic!("Error parsing DataObject, expected ':' but got: {}", s); }
-    s = s[1..].trim();
-    
-    let (val, n) = extract_value(s);
-    o.set_property(&key, val.clone());
-    if val.is_object() { val.object().decr(); }
-    if val.is_array() { val.array().decr(); }
-    s = s[n..].trim();
-    if s.starts_with("}") { break; }
-    if !s.starts_with(",") { panic!("Error parsing DataObject, expected ',' but got: {}", s); }
-    s = s[1..].trim();
-  }
-  s = s[1..].trim();
-  (o, nn-s.len())
-}
-
-/// Create a new DataArray from a JSON string.
+    Ok(())
+  }
+}
+
+fn write_object_styled<W: fmt::Write>(w:&mut W, o:&DataObject, style:Style, depth:usize) -> fmt::Result {
+  let mut pairs = o.objects();
+  if pairs.is_empty() { return w.write_str("{}"); }
+  if style.sort_keys { pairs.sort_by(|a, b| a.0.cmp(&b.0)); }
+  w.write_str("{")?;
+  for (i, (key, p)) in pairs.into_iter().enumerate() {
+    if i>0 { w.write_str(",")?; }
+    style.newline(w, depth + 1)?;
+    w.write_str("\"")?;
+    w.write_str(&escape(&key))?;
+    w.write_str(if style.indent.is_some() { "\": " } else { "\":" })?;
+    write_data_styled(w, p, style, depth + 1)?;
+  }
+  style.newline(w, depth)?;
+  w.write_str("}")
+}
+
+fn write_array_styled<W: fmt::Write>(w:&mut W, o:&DataArray, style:Style, depth:usize) -> fmt::Result {
+  let values = o.objects();
+  if values.is_empty() { return w.write_str("[]"); }
+  w.write_str("[")?;
+  for (i, p) in values.into_iter().enumerate() {
+    if i>0 { w.write_str(",")?; }
+    style.newline(w, depth + 1)?;
+    write_data_styled(w, p, style, depth + 1)?;
+  }
+  style.newline(w, depth)?;
+  w.write_str("]")
+}
+
+fn write_data_styled<W: fmt::Write>(w:&mut W, p:Data, style:Style, depth:usize) -> fmt::Result {
+  #[cfg(feature="bytes_json")]
+  if p.is_bytes() {
+    return write!(w, "{{\"{}\":\"{}\",\"{}\":\"{}\"}}", TAG_KEY, BYTES_TAG, TAG_VALUE_KEY, p.bytes().to_base64());
+  }
+  if p.is_string() || p.is_bytes() {
+    w.write_str("\"")?;
+    w.write_str(&escape(&Data::as_string(p)))?;
+    w.write_str("\"")
+  }
+  else if let Some(ts) = timestamp_string(&p) {
+    w.write_str("\"")?;
+    w.write_str(&ts)?;
+    w.write_str("\"")
+  }
+  else if p.is_object() {
+    write_object_styled(w, &p.object(), style, depth)
+  }
+  else if p.is_array() {
+    write_array_styled(w, &p.array(), style, depth)
+  }
+  else if let Data::DFloat(f) = p {
+    style.float_format.write(w, f, style.non_finite)
+  }
+  else { w.write_str(&Data::as_string(p)) }
+}
+
+/// Adapts a ```std::io::Write``` to ```fmt::Write```, keeping any IO error
+#[cfg(not(feature="no_std_support"))]
+struct IoWriter<'a, W: std::io::Write> {
+  inner: &'a mut W,
+  error: Option<std::io::Error>,
+}
+
+#[cfg(not(feature="no_std_support"))]
+impl<W: std::io::Write> fmt::Write for IoWriter<'_, W> {
+  fn write_str(&mut self, s:&str) -> fmt::Result {
+    self.inner.write_all(s.as_bytes()).map_err(|e| {
+      self.error = Some(e);
+      fmt::Error
+    })
+  }
+}
+
+#[cfg(not(feature="no_std_support"))]
+impl<W: std::io::Write> IoWriter<'_, W> {
+  fn result(self, r:fmt::Result) -> std::io::Result<()> {
+    match (r, self.error) {
+      (Ok(()), _) => Ok(()),
+      (Err(_), Some(e)) => Err(e),
+      (Err(_), None) => Err(std::io::Error::other("Error formatting JSON")),
+    }
+  }
+}
+
+/// Write a DataObject as JSON to the given ```std::io::Write```. The output is written in 
+/// many small pieces, so wrap unbuffered writers in a ```std::io::BufWriter```.
+#[cfg(not(feature="no_std_support"))]
+pub fn write_object_io<W: std::io::Write>(w:&mut W, o:&DataObject) -> std::io::Result<()> {
+  let mut writer = IoWriter { inner: w, error: None };
+  let r = write_object(&mut writer, o);
+  writer.result(r)
+}
+
+/// Write a DataArray as JSON to the given ```std::io::Write```. The output is written in 
+/// many small pieces, so wrap unbuffered writers in a ```std::io::BufWriter```.
+#[cfg(not(feature="no_std_support"))]
+pub fn write_array_io<W: std::io::Write>(w:&mut W, o:&DataArray) -> std::io::Result<()> {
+  let mut writer = IoWriter { inner: w, error: None };
+  let r = write_array(&mut writer, o);
+  writer.result(r)
+}
+
+/// How duplicate keys in a JSON object are handled when parsing
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub enum DuplicateKeyPolicy {
+  /// Keep the last value for the key
+  #[default]
+  LastWins,
+  /// Keep the first value for the key and ignore the rest
+  FirstWins,
+  /// Fail with ```ParseError::DuplicateKey```
+  Error,
+}
+
+impl DuplicateKeyPolicy {
+  fn from_u8(b:u8) -> DuplicateKeyPolicy {
+    match b {
+      1 => DuplicateKeyPolicy::FirstWins,
+      2 => DuplicateKeyPolicy::Error,
+      _ => DuplicateKeyPolicy::LastWins,
+    }
+  }
+}
+
+/// The ```DuplicateKeyPolicy``` used when parsing
+static DUPLICATE_KEY_POLICY:AtomicU8 = AtomicU8::new(0);
+
+/// Set how duplicate keys are handled when parsing JSON objects. Defaults to 
+/// ```DuplicateKeyPolicy::LastWins```.
+pub fn set_duplicate_key_policy(policy:DuplicateKeyPolicy) {
+  DUPLICATE_KEY_POLICY.store(policy as u8, Ordering::Relaxed);
+}
+
+/// Returns how duplicate keys are handled when parsing JSON objects.
+pub fn duplicate_key_policy() -> DuplicateKeyPolicy {
+  DuplicateKeyPolicy::from_u8(DUPLICATE_KEY_POLICY.load(Ordering::Relaxed))
+}
+
+/// Create a new DataObject from a JSON string. Panics if the string is not a valid JSON object.
+pub fn object_from_string(s:&str) -> DataObject {
+  match try_object_from_string(s) {
+    Ok(o) => o,
+    Err(e) => panic!("Error parsing DataObject: {}", e),
+  }
+}
+
+/// Create a new DataObject from a JSON string, or return an error and the byte offset at which 
+/// it was found if the string is not a valid JSON object. Duplicate keys are handled according 
+/// to ```duplicate_key_policy()```.
+pub fn try_object_from_string(s:&str) -> Result<DataObject, ParseErrorAt> {
+  parse_all(StrLexer::new(s), parse_object, duplicate_key_policy())
+}
+
+/// Create a new DataArray from a JSON string. Panics if the string is not a valid JSON array.
 pub fn array_from_string(s:&str) -> DataArray {
-  let (o, n) = extract_array(s);
-  if n<s.len() { panic!("Error parsing DataObject, extra characters: {}", &s[n..]); }
-  o
+  match try_array_from_string(s) {
+    Ok(a) => a,
+    Err(e) => panic!("Error parsing DataArray: {}", e),
+  }
+}
+
+/// Create a new DataArray from a JSON string, or return an error if the string is not a valid 
+/// JSON array. Duplicate keys in nested objects are handled according to 
+/// ```duplicate_key_policy()```.
+pub fn try_array_from_string(s:&str) -> Result<DataArray, ParseErrorAt> {
+  parse_all(StrLexer::new(s), parse_array, duplicate_key_policy())
+}
+
+/// Create a new DataObject from a JSON string as for ```try_object_from_string()```, but fail
+/// with ```ParseError::DuplicateKey``` if any object repeats a key, regardless of 
+/// ```duplicate_key_policy()```.
+pub fn object_from_string_strict(s:&str) -> Result<DataObject, ParseErrorAt> {
+  parse_all(StrLexer::new(s), parse_object, DuplicateKeyPolicy::Error)
+}
+
+/// Create a new DataArray from a JSON string as for ```try_array_from_string()```, but fail
+/// with ```ParseError::DuplicateKey``` if any nested object repeats a key, regardless of 
+/// ```duplicate_key_policy()```.
+pub fn array_from_string_strict(s:&str) -> Result<DataArray, ParseErrorAt> {
+  parse_all(StrLexer::new(s), parse_array, DuplicateKeyPolicy::Error)
+}
+
+/// Create a new DataObject from UTF-8 encoded JSON read one byte at a time, as for 
+/// ```try_object_from_string()```, without first collecting the input into a string. The 
+/// whole input is consumed, so that trailing characters can be reported.
+pub fn object_from_reader<R: Iterator<Item=u8>>(r:R) -> Result<DataObject, ParseErrorAt> {
+  parse_all(IterLexer::new(r), parse_object, duplicate_key_policy())
+}
+
+/// Create a new DataArray from UTF-8 encoded JSON read one byte at a time, as for 
+/// ```object_from_reader()```.
+pub fn array_from_reader<R: Iterator<Item=u8>>(r:R) -> Result<DataArray, ParseErrorAt> {
+  parse_all(IterLexer::new(r), parse_array, duplicate_key_policy())
 }
 
-fn extract_array(s:&str) -> (DataArray, usize) {
-  let mut o = DataArray::new();
-  let s = s.trim();
-  if !s.starts_with("[") { panic!("Error parsing DataArray from: {}", s); }
+/// A source of characters for the parser
+trait Lexer {
+  /// Returns the next character without consuming it, or ```None``` at the end of the input
+  fn peek(&mut self) -> Result<Option<char>, ParseError>;
   
-  let nn = s.len();
-  let mut s = s[1..].trim();
+  /// Consume the character returned by ```peek()```
+  fn bump(&mut self);
   
-  loop {
-    if s.starts_with("]") { break; }
-    let (val, n) = extract_value(s);
-    o.push_property(val.clone());
-    if val.is_object() { val.object().decr(); }
-    if val.is_array() { val.array().decr(); }
-    s = s[n..].trim();
-    if s.starts_with("]") { break; }
-    if !s.starts_with(",") { panic!("Error parsing DataObject, expected ',' but got: {}", s); }
-    s = s[1..].trim();    
-  }
-  s = s[1..].trim();
-  (o, nn-s.len())
-}
-
-fn extract_string(s:&str, c1:char, c2:char) -> Option<String> {
-  let ba = s.as_bytes();
-  if ba[0] as char != c1 { return None; }
-  let mut i = 1;
-  let n = ba.len();
-  let mut out = c1.to_string();
-  let mut ignore = false;
-  loop {
-    if i == n { break; }
-    let c = ba[i] as char;
-    out.push(c);
-    if !ignore {
-      if c == c2 { break; }
-      ignore = c == '\\';
+  /// Returns the number of bytes consumed so far
+  fn offset(&self) -> usize;
+  
+  /// Consume and return the next character
+  fn next_char(&mut self) -> Result<Option<char>, ParseError> {
+    let c = self.peek()?;
+    if c.is_some() { self.bump(); }
+    Ok(c)
+  }
+  
+  fn skip_whitespace(&mut self) -> Result<(), ParseError> {
+    while let Some(c) = self.peek()? {
+      if !c.is_whitespace() { break; }
+      self.bump();
     }
-    else { ignore = false; }
-    i += 1;
+    Ok(())
   }
-  Some(out)
 }
 
-fn extract_value(s:&str) -> (Data, usize) {
-  let n = s.len();
-  if s.starts_with("\"") {
-    let s = extract_string(s, '\"', '\"').unwrap();
-    let n = s.len();
-    let s = &s[1..n-1];
-    let s = unescape(s);
-    return (Data::DString(s.to_string()), n);
+/// Reads characters from a string slice
+struct StrLexer<'a> {
+  s: &'a str,
+  len: usize,
+}
+
+impl<'a> StrLexer<'a> {
+  fn new(s:&'a str) -> StrLexer<'a> {
+    StrLexer { s, len: s.len() }
   }
-  if s.starts_with("{") {
-    let (o, n) = extract_object(&s);
-    o.incr();
-    return (Data::DObject(o.data_ref), n);
+}
+
+impl Lexer for StrLexer<'_> {
+  fn peek(&mut self) -> Result<Option<char>, ParseError> {
+    Ok(self.s.chars().next())
   }
-  if s.starts_with("[") {
-    let (o, n) = extract_array(&s);
-    o.incr();
-    return (Data::DArray(o.data_ref), n);
+  
+  fn bump(&mut self) {
+    if let Some(c) = self.s.chars().next() { self.s = &self.s[c.len_utf8()..]; }
   }
-  if n>=4 && s[0..4].to_lowercase() == "null" {
-    return (Data::DNull, 4);
+  
+  fn offset(&self) -> usize {
+    self.len - self.s.len()
   }
-  if n>=4 && s[0..4].to_lowercase() == "true" {
-    return (Data::DBoolean(true), 4);
+}
+
+/// Decodes characters from an iterator over UTF-8 bytes
+struct IterLexer<R: Iterator<Item=u8>> {
+  r: R,
+  peeked: Option<char>,
+  offset: usize,
+}
+
+impl<R: Iterator<Item=u8>> IterLexer<R> {
+  fn new(r:R) -> IterLexer<R> {
+    IterLexer { r, peeked: None, offset: 0 }
+  }
+}
+
+impl<R: Iterator<Item=u8>> Lexer for IterLexer<R> {
+  fn peek(&mut self) -> Result<Option<char>, ParseError> {
+    if self.peeked.is_some() { return Ok(self.peeked); }
+    let Some(b) = self.r.next() else { return Ok(None); };
+    let n = match b {
+      0x00..=0x7F => 1,
+      0xC2..=0xDF => 2,
+      0xE0..=0xEF => 3,
+      0xF0..=0xF4 => 4,
+      _ => return Err(ParseError::InvalidUtf8),
+    };
+    let mut buf = [b, 0, 0, 0];
+    for x in buf.iter_mut().take(n).skip(1) {
+      *x = self.r.next().ok_or(ParseError::InvalidUtf8)?;
+    }
+    let c = core::str::from_utf8(&buf[..n]).map_err(|_| ParseError::InvalidUtf8)?.chars().next();
+    self.peeked = c;
+    Ok(c)
   }
-  if n>=5 && s[0..5].to_lowercase() == "false" {
-    return (Data::DBoolean(false), 5);
+  
+  fn bump(&mut self) {
+    if let Some(c) = self.peeked.take() { self.offset += c.len_utf8(); }
   }
   
-  let ba = s.as_bytes();
-  let mut out = "".to_string();
-  let mut i = 0;
+  fn offset(&self) -> usize {
+    self.offset
+  }
+}
+
+/// Parse a value with the given function and check that nothing but whitespace follows it
+fn parse_all<L: Lexer, T>(mut l:L, parse:fn(&mut L, DuplicateKeyPolicy) -> Result<T, ParseError>, policy:DuplicateKeyPolicy) -> Result<T, ParseErrorAt> {
+  let v = parse(&mut l, policy).map_err(|error| ParseErrorAt { error, offset: l.offset() })?;
+  end_of_input(&mut l).map_err(|(error, offset)| ParseErrorAt { error, offset })?;
+  Ok(v)
+}
+
+/// Check that only whitespace remains, or return the error and the offset of the first 
+/// character that is not whitespace
+fn end_of_input<L: Lexer>(l:&mut L) -> Result<(), (ParseError, usize)> {
+  l.skip_whitespace().map_err(|e| (e, l.offset()))?;
+  let offset = l.offset();
+  let mut rest = String::new();
+  while let Some(c) = l.next_char().map_err(|e| (e, l.offset()))? {
+    rest.push(c);
+  }
+  if rest.is_empty() { Ok(()) } else { Err((ParseError::TrailingCharacters(rest), offset)) }
+}
+
+/// Returns the error for an unexpected next character (or end of input)
+fn unexpected<L: Lexer>(l:&mut L) -> ParseError {
+  match l.peek() {
+    Ok(Some(c)) => ParseError::UnexpectedChar(c),
+    Ok(None) => ParseError::UnexpectedEnd,
+    Err(e) => e,
+  }
+}
+
+/// Skip whitespace, then consume the given character or fail
+fn expect<L: Lexer>(l:&mut L, c:char) -> Result<(), ParseError> {
+  if !consume(l, c)? { return Err(unexpected(l)); }
+  Ok(())
+}
+
+/// Skip whitespace, then consume the given character if it is next
+fn consume<L: Lexer>(l:&mut L, c:char) -> Result<bool, ParseError> {
+  l.skip_whitespace()?;
+  if l.peek()? != Some(c) { return Ok(false); }
+  l.bump();
+  Ok(true)
+}
+
+fn parse_object<L: Lexer>(l:&mut L, policy:DuplicateKeyPolicy) -> Result<DataObject, ParseError> {
+  expect(l, '{')?;
+  let o = DataObject::new();
+  if consume(l, '}')? { return Ok(o); }
   loop {
-    if i == n { break; }
-    let c = ba[i] as char;
-    if c == ',' || c == '}' || c == ']' { break; }
-    out.push(c);
-    i += 1;
+    expect(l, '"')?;
+    let key = parse_string_content(l)?;
+    expect(l, ':')?;
+    let val = parse_value(l, policy)?;
+    insert_property(&o, key, val, policy)?;
+    if consume(l, '}')? { return Ok(o); }
+    expect(l, ',')?;
+  }
+}
+
+/// Add a parsed value to the object, transferring the value's reference to the object. Does not
+/// call ```on_change()``` callbacks, since the object is still being parsed and none can be 
+/// registered on it.
+fn insert_property(o:&DataObject, key:String, val:Data, policy:DuplicateKeyPolicy) -> Result<(), ParseError> {
+  let heap = &mut oheap().lock();
+  let map = heap.get(o.data_ref);
+  if map.contains_key(&key) && policy != DuplicateKeyPolicy::LastWins {
+    val.queue_decr();
+    if policy == DuplicateKeyPolicy::Error { return Err(ParseError::DuplicateKey(key)); }
+    return Ok(());
   }
-  if out.contains(".") {
-    let f = out.trim().parse::<f64>().unwrap();
-    return (Data::DFloat(f), i);
+  if let Some(old) = map.insert(key, val) {
+    old.queue_decr();
   }
-  else {
-    let f = out.trim().parse::<i64>().unwrap();
-    return (Data::DInt(f), i);
+  Ok(())
+}
+
+fn parse_array<L: Lexer>(l:&mut L, policy:DuplicateKeyPolicy) -> Result<DataArray, ParseError> {
+  expect(l, '[')?;
+  let mut vec = Vec::new();
+  if let Err(e) = parse_elements(l, policy, &mut vec) {
+    for d in &vec {
+      d.queue_decr();
+    }
+    return Err(e);
+  }
+  // Transfer the references of the values to the array, moving the whole Vec onto the heap
+  let data_ref = aheap().lock().push(ArrayValues::Data(vec));
+  Ok(DataArray { data_ref })
+}
+
+/// Parse the values of an array whose opening bracket has been consumed, up to and including
+/// the closing bracket
+fn parse_elements<L: Lexer>(l:&mut L, policy:DuplicateKeyPolicy, vec:&mut Vec<Data>) -> Result<(), ParseError> {
+  if consume(l, ']')? { return Ok(()); }
+  loop {
+    vec.push(parse_value(l, policy)?);
+    if consume(l, ']')? { return Ok(()); }
+    expect(l, ',')?;
+  }
+}
+
+/// Parse the next value. An object or array is returned holding the reference of the newly 
+/// created instance, which the caller must transfer or release.
+fn parse_value<L: Lexer>(l:&mut L, policy:DuplicateKeyPolicy) -> Result<Data, ParseError> {
+  l.skip_whitespace()?;
+  match l.peek()? {
+    Some('"') => {
+      l.bump();
+      return Ok(Data::DString(parse_string_content(l)?));
+    },
+    Some('{') => {
+      let o = parse_object(l, policy)?;
+      #[cfg(feature="bytes_json")]
+      if let Some(b) = DataBytes::from_tagged(&o) {
+        let d = Data::DBytes(b.data_ref);
+        core::mem::forget(b);
+        return Ok(d);
+      }
+      let d = Data::DObject(o.data_ref);
+      core::mem::forget(o);
+      return Ok(d);
+    },
+    Some('[') => {
+      let a = parse_array(l, policy)?;
+      let d = Data::DArray(a.data_ref);
+      core::mem::forget(a);
+      return Ok(d);
+    },
+    _ => {},
+  }
+  
+  let mut token = String::new();
+  while let Some(c) = l.peek()? {
+    if c == ',' || c == '}' || c == ']' || c.is_whitespace() { break; }
+    token.push(c);
+    l.bump();
+  }
+  if token.is_empty() { return Err(unexpected(l)); }
+  let lower = token.to_lowercase();
+  if lower == "null" { Ok(Data::DNull) }
+  else if lower == "true" { Ok(Data::DBoolean(true)) }
+  else if lower == "false" { Ok(Data::DBoolean(false)) }
+  else { parse_number(&token) }
+}
+
+fn parse_number(token:&str) -> Result<Data, ParseError> {
+  let first = token.chars().next().unwrap_or(' ');
+  if first != '-' && !first.is_ascii_digit() { return Err(ParseError::UnexpectedChar(first)); }
+  let invalid = || ParseError::InvalidNumber(token.to_string());
+  if !token.contains(['.', 'e', 'E']) {
+    if let Ok(i) = token.parse::<i64>() { return Ok(Data::DInt(i)); }
+  }
+  match token.parse::<f64>() {
+    Ok(f) if f.is_finite() => Ok(Data::DFloat(f)),
+    _ => Err(invalid()),
+  }
+}
+
+/// Parse the rest of a string whose opening quote has been consumed, decoding escape sequences
+fn parse_string_content<L: Lexer>(l:&mut L) -> Result<String, ParseError> {
+  let mut out = String::new();
+  // A high surrogate waiting for the low surrogate that should follow it
+  let mut high:Option<u32> = None;
+  loop {
+    let c = l.next_char()?.ok_or(ParseError::UnexpectedEnd)?;
+    let e = if c == '\\' { Some(l.next_char()?.ok_or(ParseError::UnexpectedEnd)?) } else { None };
+    if e == Some('u') {
+      let cp = parse_hex4(l)?;
+      if let Some(h) = high.take() {
+        if (0xDC00..0xE000).contains(&cp) {
+          out.push(char::from_u32(0x10000 + ((h - 0xD800) << 10) + (cp - 0xDC00)).unwrap_or('\u{FFFD}'));
+          continue;
+        }
+        out.push('\u{FFFD}');
+      }
+      if (0xD800..0xDC00).contains(&cp) { high = Some(cp); }
+      // Unpaired surrogates are replaced rather than rejected
+      else { out.push(char::from_u32(cp).unwrap_or('\u{FFFD}')); }
+      continue;
+    }
+    if high.take().is_some() { out.push('\u{FFFD}'); }
+    match e {
+      None if c == '"' => return Ok(out),
+      None => out.push(c),
+      Some('"') => out.push('"'),
+      Some('\\') => out.push('\\'),
+      Some('/') => out.push('/'),
+      Some('b') => out.push('\u{8}'),
+      Some('f') => out.push('\u{c}'),
+      Some('n') => out.push('\n'),
+      Some('r') => out.push('\r'),
+      Some('t') => out.push('\t'),
+      Some(e) => return Err(ParseError::InvalidEscape(format!("\\{}", e))),
+    }
+  }
+}
+
+fn parse_hex4<L: Lexer>(l:&mut L) -> Result<u32, ParseError> {
+  let mut cp = 0;
+  let mut hex = String::new();
+  for _ in 0..4 {
+    let c = l.next_char()?.ok_or(ParseError::UnexpectedEnd)?;
+    hex.push(c);
+    match c.to_digit(16) {
+      Some(d) => cp = cp * 16 + d,
+      None => return Err(ParseError::InvalidEscape(format!("\\u{}", hex))),
+    }
   }
+  Ok(cp)
 }
 
 /// Unescape the string
@@ -213,6 +720,7 @@ pub fn unescape(s:&str) -> String {
   let s = str::replace(&s, "\\n", "\n");
   let s = str::replace(&s, "\\r", "\r");
   let s = str::replace(&s, "\\t", "\t");
+  let s = str::replace(&s, "\\/", "/");
   let s = str::replace(&s, "\\\\", "\\");
   s
 }
@@ -227,6 +735,7 @@ pub fn escape(s:&str) -> String {
   let s = str::replace(&s, "\n", "\\n");
   let s = str::replace(&s, "\r", "\\r");
   let s = str::replace(&s, "\t", "\\t");
+  if is_escape_slash() { return str::replace(&s, "/", "\\/"); }
   s
 }
 
@@ -11,7 +11,8 @@
 //! NData supports objects, arrays, strings, integers, floats, 
 //! booleans, byte buffers, and null. DataObject, DataArray, and DataBytes instances 
 //! maintain reference counts. Garbage collection is performed manually by calling the 
-//! type's gc() function.
+//! type's gc() function, or automatically once enough handles have been dropped if a 
+//! threshold is set with set_gc_threshold().
 
 pub mod heap;
 pub mod usizemap;
@@ -20,25 +21,72 @@ pub mod dataobject;
 pub mod dataarray;
 pub mod databytes;
 pub mod sharedmutex;
+pub mod ndataerror;
 
 #[cfg(not(feature="serde_support"))]
 pub mod json_util;
 
+use core::sync::atomic::AtomicUsize;
+use core::sync::atomic::Ordering;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use crate::heap::*;
+use crate::data::*;
 use crate::dataobject::*;
 use crate::dataarray::*;
 use crate::databytes::*;
+use crate::sharedmutex::*;
+use crate::ndataerror::*;
+
+/// Identifies the version of ndata that created an ```NDataConfig```, since the layout of the 
+/// shared storage may differ between versions.
+pub const NDATA_VERSION:u64 = version_id(env!("CARGO_PKG_VERSION"));
+
+/// FNV-1a hash of the crate version string
+const fn version_id(s:&str) -> u64 {
+  let b = s.as_bytes();
+  let mut h:u64 = 0xcbf29ce484222325;
+  let mut i = 0;
+  while i < b.len() {
+    h ^= b[i] as u64;
+    h = h.wrapping_mul(0x100000001b3);
+    i += 1;
+  }
+  h
+}
 
 #[derive(Debug, Default, Copy, Clone)]
 pub struct NDataConfig{
   data: (((u64,u64),(u64,u64)),((u64,u64),(u64,u64)),((u64,u64),(u64,u64))),
+  /// The ```NDATA_VERSION``` of the process that created this configuration, or 0 if unknown
+  version: u64,
+  /// The data_ref of the object returned by ```globals()```, or ```u64::MAX``` if there is none
+  globals: u64,
 }
 
 impl NDataConfig {
-  pub fn to_string(&self) -> String{
+  fn values(&self) -> Vec<u64> {
     let (((a, b), (c, d)), ((e, f), (g, h)), ((i, j), (k, l))) = self.data;
-    let v = vec![a,b,c,d,e,f,g,h,i,j,k,l];
+    vec![a,b,c,d,e,f,g,h,i,j,k,l,self.version,self.globals]
+  }
+  
+  fn from_values(x:Vec<u64>) -> Self {
+    NDataConfig{
+      data: (((x[0],x[1]),(x[2],x[3])),((x[4],x[5]),(x[6],x[7])),((x[8],x[9]),(x[10],x[11]))),
+      version: if x.len() > 12 { x[12] } else { 0 },
+      globals: if x.len() > 13 { x[13] } else { u64::MAX },
+    }
+  }
+  
+  /// Returns the ```NDATA_VERSION``` of the process that created this configuration, or 0 if 
+  /// it was read from a string written by an older version of ndata.
+  pub fn version(&self) -> u64 {
+    self.version
+  }
+  
+  pub fn to_string(&self) -> String{
     let mut s = "".to_string();
-    for x in v { s += &format!( "{:016X}", x); }
+    for x in self.values() { s += &format!( "{:016X}", x); }
     s
   }
 
@@ -49,34 +97,291 @@ impl NDataConfig {
       x.push(a.unwrap());
       s = s[16..].to_string();
     }
-    NDataConfig{
-      data: (((x[0],x[1]),(x[2],x[3])),((x[4],x[5]),(x[6],x[7])),((x[8],x[9]),(x[10],x[11]))),
-    }
+    NDataConfig::from_values(x)
+  }
+  
+  /// Returns this configuration as big-endian bytes, in the same order as ```to_string()```.
+  pub fn to_bytes(&self) -> Vec<u8> {
+    let mut v = Vec::new();
+    for x in self.values() { v.extend_from_slice(&x.to_be_bytes()); }
+    v
+  }
+  
+  /// Read a configuration written by ```to_bytes()```.
+  pub fn from_bytes(b:&[u8]) -> Self {
+    let x = b.chunks_exact(8).map(|c| u64::from_be_bytes(c.try_into().unwrap())).collect();
+    NDataConfig::from_values(x)
   }
 }
 
 /// Initialize global storage of data. Call only once at startup.
 pub fn init() -> NDataConfig {
+  init_with_heaps(Heap::new(), Heap::new(), Heap::new())
+}
+
+/// Initialize global storage of data using the given (empty) heaps for objects, arrays and byte
+/// buffers, such as heaps created with ```Heap::in_region()``` to keep them in pre-allocated 
+/// memory. Call only once at startup, instead of ```init()```.
+///
+/// ```reset()``` replaces these heaps with ordinary ones.
+pub fn init_with_heaps(oheap:Heap<HashMap<String,Data>>, aheap:Heap<ArrayValues>, bheap:Heap<DataStream>) -> NDataConfig {
   NDataConfig{
-    data: (DataObject::init(), DataArray::init(), DataBytes::init()),
+    data: (DataObject::init_with_heap(oheap), DataArray::init_with_heap(aheap), DataBytes::init_with_heap(bheap)),
+    version: NDATA_VERSION,
+    globals: u64::MAX,
   }
 }
 
+/// The configuration returned by ```init_once()```
+#[cfg(not(feature="no_std_support"))]
+static INIT:std::sync::OnceLock<NDataConfig> = std::sync::OnceLock::new();
+
+/// Initialize global storage of data if it has not already been initialized by this function,
+/// and return the configuration. Safe to call from several threads at once: the first caller 
+/// initializes storage, the others wait for it to finish, and all receive the same 
+/// configuration. Do not combine with ```init()```.
+#[cfg(not(feature="no_std_support"))]
+pub fn init_once() -> NDataConfig {
+  *INIT.get_or_init(init)
+}
+
+/// The data_ref of the object returned by ```globals()```
+static GLOBALS:AtomicUsize = AtomicUsize::new(usize::MAX);
+
+/// Initialize global storage of data and create a globals object that is never garbage 
+/// collected. Call only once at startup.
+///
+/// The globals object can be retrieved from anywhere in this process with ```ndata::globals()```,
+/// and from processes that pass the returned configuration to ```mirror()```.
+pub fn init_with_globals() -> (NDataConfig, DataObject) {
+  let mut config = init();
+  let o = DataObject::new();
+  o.incr();
+  GLOBALS.store(o.data_ref, Ordering::Release);
+  config.globals = o.data_ref as u64;
+  (config, o)
+}
+
+/// Returns the globals object created by ```init_with_globals()``` in this process or in the 
+/// process this one mirrors, or panics if there is none.
+pub fn globals() -> DataObject {
+  let data_ref = GLOBALS.load(Ordering::Acquire);
+  if data_ref == usize::MAX { panic!("Globals not initialized, call ndata::init_with_globals() first"); }
+  DataObject::get(data_ref)
+}
+
 /// Mirror global storage of data from another process. Call only once at startup.
+///
+/// Panics if the configuration was created by a different version of ndata. See 
+/// ```try_mirror()```.
 pub fn mirror(data_ref:NDataConfig) {
+  if let Err(e) = try_mirror(data_ref) { panic!("{}", e); }
+}
+
+/// Mirror global storage of data from another process, or return an error without mirroring
+/// if the configuration was created by a different version of ndata (including configurations 
+/// without a version). Call only once at startup.
+pub fn try_mirror(data_ref:NDataConfig) -> Result<(), NDataError> {
+  if data_ref.version != NDATA_VERSION {
+    return Err(NDataError::VersionMismatch { expected: NDATA_VERSION, found: data_ref.version });
+  }
   DataObject::mirror(data_ref.data.0.0, data_ref.data.0.1);
   DataArray::mirror(data_ref.data.1.0, data_ref.data.1.1);
   DataBytes::mirror(data_ref.data.2.0, data_ref.data.2.1);
+  GLOBALS.store(data_ref.globals as usize, Ordering::Release);
+  Ok(())
+}
+
+/// Set how this process waits when global storage is locked by another thread.
+/// The default is ```ContentionPolicy::Backoff```.
+pub fn set_contention_policy(policy:ContentionPolicy) {
+  DataObject::set_contention_policy(policy);
+  DataArray::set_contention_policy(policy);
+  DataBytes::set_contention_policy(policy);
 }
 
 /// Perform garbage collection. Instances will not be removed from the heap until
 /// ```NData::gc()``` is called.
+///
+/// Like the per-type collectors, returns immediately when called re-entrantly from this thread.
 pub fn gc() {
   DataObject::gc();
   DataArray::gc();
   DataBytes::gc();
 }
 
+/// Discard every object, array and byte buffer, empty the drop queues, and forget any 
+/// callbacks registered with ```DataObject::on_change()```, as if ```init()``` had just been 
+/// called. If there was a globals object, it is replaced with a new empty one, so 
+/// ```globals()``` keeps working. Intended for isolating tests from each other: start each 
+/// test with ```init_once()``` followed by ```reset()```, since calling ```init()``` or 
+/// ```init_with_globals()``` a second time panics.
+///
+/// Returns ```false``` without changing anything if the heaps have not been initialized or any
+/// heap or drop queue is currently locked.
+///
+/// All existing handles and data references become invalid. Handles must be dropped (or passed
+/// to ```core::mem::forget()```) before calling this, since dropping one afterwards would 
+/// release an instance created after the reset.
+pub fn reset() -> bool {
+  let (Some(mut oheap), Some(mut aheap), Some(mut bheap)) = (oheap().try_lock_if_initialized(), aheap().try_lock_if_initialized(), bheap().try_lock_if_initialized()) else { return false; };
+  let (Some(mut odrop), Some(mut adrop), Some(mut bdrop)) = (odrop().try_lock_if_initialized(), adrop().try_lock_if_initialized(), bdrop().try_lock_if_initialized()) else { return false; };
+  *oheap = Heap::new();
+  *aheap = Heap::new();
+  *bheap = Heap::new();
+  odrop.clear();
+  adrop.clear();
+  bdrop.clear();
+  drop((oheap, aheap, bheap, odrop, adrop, bdrop));
+  clear_observers();
+  if GLOBALS.load(Ordering::Acquire) != usize::MAX {
+    let o = DataObject::new();
+    o.incr();
+    GLOBALS.store(o.data_ref, Ordering::Release);
+  }
+  true
+}
+
+/// Perform garbage collection on all heaps as for ```gc()```, skipping any heap whose lock is 
+/// already held instead of waiting for it.
+pub fn try_gc() {
+  DataObject::try_gc();
+  DataArray::try_gc();
+  DataBytes::try_gc();
+}
+
+/// The drop queue length above which dropping a handle triggers garbage collection, or 0
+static GC_THRESHOLD:AtomicUsize = AtomicUsize::new(0);
+
+/// Set the number of dropped ```DataObject```, ```DataArray``` or ```DataBytes``` handles 
+/// waiting in a drop queue above which the next drop performs garbage collection, as for 
+/// ```try_gc()```. Defaults to 0, which disables automatic garbage collection.
+///
+/// When enabled, dropping a handle may take the heap locks and remove instances from the heap. 
+/// Values removed with ```pop_property()``` and similar functions then remain valid only until
+/// the next handle is dropped, rather than until the next explicit ```gc()```.
+pub fn set_gc_threshold(n:usize) {
+  GC_THRESHOLD.store(n, Ordering::Relaxed);
+}
+
+/// Returns the drop queue length above which dropping a handle performs garbage collection, or
+/// 0 if automatic garbage collection is disabled.
+pub fn gc_threshold() -> usize {
+  GC_THRESHOLD.load(Ordering::Relaxed)
+}
+
+/// Returns ```true``` if a drop queue of the given length should trigger garbage collection
+pub(crate) fn over_gc_threshold(queued:usize) -> bool {
+  let n = gc_threshold();
+  n != 0 && queued > n
+}
+
+/// Release unused memory from the object, array, and byte buffer heaps, as after a 
+/// ```gc()``` that removed many instances, and return the number of slots released. Existing 
+/// handles and data references remain valid.
+pub fn compact() -> usize {
+  let n = oheap().lock().shrink();
+  let n = n + aheap().lock().shrink();
+  n + bheap().lock().shrink()
+}
+
+/// An object (```true```) or array (```false```) on the heap
+type Node = (bool, usize);
+
+fn node(d:&Data) -> Option<Node> {
+  match d {
+    Data::DObject(i) => Some((true, *i)),
+    Data::DArray(i) => Some((false, *i)),
+    _ => None,
+  }
+}
+
+/// Find the objects and arrays that are only referenced by other objects and arrays that are 
+/// not themselves referenced from outside the heaps. Any reference count not accounted for by 
+/// references between instances belongs to a handle or other external owner, so those 
+/// instances and everything reachable from them are live.
+fn find_unreachable(oheap:&mut Heap<HashMap<String,Data>>, aheap:&mut Heap<ArrayValues>) -> Vec<Node> {
+  let mut edges = HashMap::<Node, Vec<Node>>::new();
+  oheap.for_each_mut(|i, map| { edges.insert((true, i), map.values().filter_map(node).collect()); });
+  aheap.for_each_mut(|i, vec| { edges.insert((false, i), vec.instances().iter().filter_map(node).collect()); });
+  let mut internal = HashMap::<Node, usize>::new();
+  for n in edges.values().flatten() {
+    *internal.entry(*n).or_insert(0) += 1;
+  }
+  let mut stack = Vec::new();
+  for n in edges.keys() {
+    let count = if n.0 { oheap.count(n.1) } else { aheap.count(n.1) };
+    if count > internal.get(n).copied().unwrap_or(0) { stack.push(*n); }
+  }
+  let mut live = HashSet::<Node>::new();
+  while let Some(n) = stack.pop() {
+    if live.insert(n) {
+      if let Some(e) = edges.get(&n) { stack.extend(e); }
+    }
+  }
+  let mut dead:Vec<Node> = edges.into_keys().filter(|n| !live.contains(n)).collect();
+  dead.sort();
+  dead
+}
+
+/// Returns the objects and arrays that can no longer be reached from any handle because they 
+/// are only referenced through reference cycles, such as an object holding an array that holds
+/// the object. These are never removed by ```gc()```. Performs ```gc()``` first.
+pub fn detect_cycles() -> Vec<Data> {
+  gc();
+  let oheap = &mut oheap().lock();
+  let aheap = &mut aheap().lock();
+  find_unreachable(oheap, aheap).into_iter().map(|(o, i)| if o { Data::DObject(i) } else { Data::DArray(i) }).collect()
+}
+
+/// Remove the objects and arrays returned by ```detect_cycles()``` from the heap, releasing 
+/// anything else they reference, and return how many were removed. Performs ```gc()``` first.
+///
+/// No roots need to be registered: every handle is counted in the reference count of its 
+/// instance, so anything reachable from a handle is kept. As with ```gc()```, a ```Data``` 
+/// copied out of an instance does not keep it alive.
+pub fn gc_cycles() -> usize {
+  gc();
+  let n = {
+    let oheap = &mut oheap().lock();
+    let aheap = &mut aheap().lock();
+    let dead = find_unreachable(oheap, aheap);
+    let is_dead:HashSet<Node> = dead.iter().copied().collect();
+    let mut released = Vec::<Data>::new();
+    for (o, i) in &dead {
+      let values:Vec<Data> = if *o { oheap.get(*i).drain().map(|(_k, v)| v).collect() }
+                             else { core::mem::take(aheap.get(*i).values()) };
+      released.extend(values.into_iter().filter(|v| !node(v).is_some_and(|n| is_dead.contains(&n))));
+    }
+    for (o, i) in &dead {
+      if *o {
+        oheap.remove(*i);
+        DataObject::remove_observers(*i);
+      }
+      else { aheap.remove(*i); }
+    }
+    for v in &released {
+      if let Data::DObject(i) = v { DataObject::delete(oheap, *i, aheap); }
+      else if let Data::DArray(i) = v { DataArray::delete(aheap, *i, oheap); }
+    }
+    for v in &released {
+      if v.is_bytes() { v.queue_decr(); }
+    }
+    dead.len()
+  };
+  DataBytes::gc();
+  n
+}
+
+/// Remove any instances whose reference count is zero from the object, array, and byte buffer
+/// heaps and return how many were removed. This is a recovery tool for heaps corrupted by 
+/// unbalanced manual reference counting, and is not needed in normal use.
+pub fn sweep() -> usize {
+  let n = oheap().lock().sweep_zero();
+  let n = n + aheap().lock().sweep_zero();
+  n + bheap().lock().sweep_zero()
+}
+
 /// Prints the objects currently stored in the heap
 #[cfg(not(feature="no_std_support"))]
 pub fn print_heap() {
@@ -87,3 +392,19 @@ pub fn print_heap() {
   println!("------------------------------");
 }
 
+
+/// Install a panic hook that prints the objects currently stored in the heap before calling 
+/// the previously installed panic hook. Heaps that are locked at the time of the panic are 
+/// reported as locked rather than waited on.
+#[cfg(not(feature="no_std_support"))]
+pub fn install_panic_reporter() {
+  let previous = std::panic::take_hook();
+  std::panic::set_hook(Box::new(move |info| {
+    eprintln!("------------ HEAP ------------");
+    DataObject::print_heap_at_panic();
+    DataArray::print_heap_at_panic();
+    DataBytes::print_heap_at_panic();
+    eprintln!("------------------------------");
+    previous(info);
+  }));
+}
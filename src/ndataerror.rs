@@ -0,0 +1,170 @@
+extern crate alloc;
+use core::fmt;
+
+#[cfg(feature="no_std_support")]
+use alloc::string::String;
+#[cfg(feature="no_std_support")]
+use alloc::boxed::Box;
+
+/// Errors returned by the fallible (```try_```) accessors
+#[derive(Debug, Clone, PartialEq)]
+pub enum NDataError {
+  /// The index is not within the bounds of the array
+  IndexOutOfBounds {
+    /// The requested index
+    index: usize,
+    /// The length of the array
+    len: usize,
+  },
+  /// The object does not contain the key
+  KeyNotFound(String),
+  /// The path segment is not a valid array index
+  InvalidIndex(String),
+  /// The value is not of the requested type
+  WrongDataType {
+    /// The requested type
+    expected: &'static str,
+    /// The type of the stored value
+    found: &'static str,
+  },
+  /// An element of the array is not of the requested type
+  WrongElementType {
+    /// The index of the element
+    index: usize,
+    /// The requested type
+    expected: &'static str,
+    /// The type of the element
+    found: &'static str,
+  },
+  /// The bytes are not valid UTF-8
+  InvalidUtf8 {
+    /// The length of the longest valid UTF-8 prefix
+    valid_up_to: usize,
+  },
+  /// The configuration was created by a different version of ndata
+  VersionMismatch {
+    /// The version of ndata in this process
+    expected: u64,
+    /// The version recorded in the configuration
+    found: u64,
+  },
+  /// The data reference is not on the object heap
+  InvalidObjectRef(usize),
+  /// The string is not valid base64
+  InvalidBase64 {
+    /// The byte offset of the first invalid character, or the length of the string if it ends
+    /// early
+    position: usize,
+  },
+  /// The stream has been closed for reading
+  StreamClosed,
+  /// A segment of a path could not be followed
+  Path {
+    /// The path up to and including the segment that failed
+    path: String,
+    /// Why the segment could not be followed
+    error: Box<NDataError>,
+  },
+  /// The JSON could not be parsed
+  Parse {
+    /// What went wrong
+    error: ParseError,
+    /// The number of bytes of input consumed when the error was found, if known
+    offset: Option<usize>,
+  },
+}
+
+impl fmt::Display for NDataError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      NDataError::IndexOutOfBounds { index, len } => write!(f, "Index {} out of bounds for array of length {}", index, len),
+      NDataError::KeyNotFound(key) => write!(f, "Object does not have key {}", key),
+      NDataError::InvalidIndex(seg) => write!(f, "{} is not a valid array index", seg),
+      NDataError::WrongDataType { expected, found } => write!(f, "Expected {} but found {}", expected, found),
+      NDataError::WrongElementType { index, expected, found } => write!(f, "Expected {} but found {} at index {}", expected, found, index),
+      NDataError::InvalidUtf8 { valid_up_to } => write!(f, "Invalid UTF-8 after byte {}", valid_up_to),
+      NDataError::VersionMismatch { expected, found } => write!(f, "Expected ndata version {:016X} but found {:016X}", expected, found),
+      NDataError::InvalidObjectRef(data_ref) => write!(f, "No object with data_ref {} on the heap", data_ref),
+      NDataError::InvalidBase64 { position } => write!(f, "Invalid base64 at byte {}", position),
+      NDataError::StreamClosed => write!(f, "Attempt to read from closed data stream"),
+      NDataError::Path { path, error } => write!(f, "At {}: {}", path, error),
+      NDataError::Parse { error, offset: Some(offset) } => write!(f, "Error parsing JSON: {} at byte {}", error, offset),
+      NDataError::Parse { error, offset: None } => write!(f, "Error parsing JSON: {}", error),
+    }
+  }
+}
+
+#[cfg(not(feature="no_std_support"))]
+impl std::error::Error for NDataError {}
+
+impl From<ParseError> for NDataError {
+  fn from(e:ParseError) -> NDataError {
+    NDataError::Parse { error: e, offset: None }
+  }
+}
+
+impl From<ParseErrorAt> for NDataError {
+  fn from(e:ParseErrorAt) -> NDataError {
+    NDataError::Parse { error: e.error, offset: Some(e.offset) }
+  }
+}
+
+/// Errors returned when parsing JSON
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseError {
+  /// The input ended before the value was complete
+  UnexpectedEnd,
+  /// The character is not valid at this point in the input
+  UnexpectedChar(char),
+  /// The token is not a valid number
+  InvalidNumber(String),
+  /// The string contains an invalid escape sequence
+  InvalidEscape(String),
+  /// The input continues after the end of the value
+  TrailingCharacters(String),
+  /// The object contains the key more than once
+  DuplicateKey(String),
+  /// The input is not valid UTF-8
+  InvalidUtf8,
+}
+
+impl fmt::Display for ParseError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      ParseError::UnexpectedEnd => write!(f, "Unexpected end of input"),
+      ParseError::UnexpectedChar(c) => write!(f, "Unexpected character '{}'", c),
+      ParseError::InvalidNumber(s) => write!(f, "Invalid number '{}'", s),
+      ParseError::InvalidEscape(s) => write!(f, "Invalid escape sequence '{}'", s),
+      ParseError::TrailingCharacters(s) => write!(f, "Extra characters after value: '{}'", s),
+      ParseError::DuplicateKey(k) => write!(f, "Duplicate key '{}'", k),
+      ParseError::InvalidUtf8 => write!(f, "Invalid UTF-8"),
+    }
+  }
+}
+
+#[cfg(not(feature="no_std_support"))]
+impl std::error::Error for ParseError {}
+
+/// A ```ParseError``` together with where in the input it was found
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseErrorAt {
+  /// What went wrong
+  pub error: ParseError,
+  /// The number of bytes of input consumed when the error was found
+  pub offset: usize,
+}
+
+impl fmt::Display for ParseErrorAt {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{} at byte {}", self.error, self.offset)
+  }
+}
+
+#[cfg(not(feature="no_std_support"))]
+impl std::error::Error for ParseErrorAt {}
+
+impl From<ParseErrorAt> for ParseError {
+  fn from(e:ParseErrorAt) -> ParseError {
+    e.error
+  }
+}
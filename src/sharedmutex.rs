@@ -2,12 +2,81 @@
 /// https://mnwa.medium.com/building-a-stupid-mutex-in-the-rust-d55886538889
 
 use core::sync::atomic::AtomicBool;
+use core::sync::atomic::AtomicU8;
+#[cfg(feature="lock_warnings")]
+use core::sync::atomic::AtomicU32;
+#[cfg(feature="lock_warnings")]
+use core::sync::atomic::AtomicPtr;
 use core::sync::atomic::Ordering;
 use core::ops::Deref;
 use core::ops::DerefMut;
 use core::hint::spin_loop;
 use core::cell::UnsafeCell;
 
+/// The longest delay (in spins) between attempts to acquire a contended mutex
+const MAX_BACKOFF:u32 = 1 << 10;
+
+/// The number of failed attempts to acquire a mutex before a warning is reported
+#[cfg(feature="lock_warnings")]
+static LOCK_WARNING_ATTEMPTS:AtomicU32 = AtomicU32::new(1 << 20);
+
+/// The function set by ```set_lock_warning_handler()```, or null for the default
+#[cfg(feature="lock_warnings")]
+static LOCK_WARNING_HANDLER:AtomicPtr<()> = AtomicPtr::new(core::ptr::null_mut());
+
+/// Set the number of failed attempts to acquire a ```SharedMutex``` after which a warning is 
+/// reported. The default is 2^20. Requires the "lock_warnings" feature.
+#[cfg(feature="lock_warnings")]
+pub fn set_lock_warning_attempts(attempts:u32) {
+  LOCK_WARNING_ATTEMPTS.store(attempts, Ordering::Relaxed);
+}
+
+/// Set the function called when a thread has waited too long to acquire a ```SharedMutex```. 
+/// It receives the address of the lock and the number of failed attempts so far. The default 
+/// prints a warning to stderr, or does nothing when the "no_std_support" feature is enabled.
+/// Requires the "lock_warnings" feature.
+#[cfg(feature="lock_warnings")]
+pub fn set_lock_warning_handler(handler:fn(u64, u32)) {
+  LOCK_WARNING_HANDLER.store(handler as *mut (), Ordering::Release);
+}
+
+#[cfg(feature="lock_warnings")]
+fn warn_lock(lock:u64, attempts:u32) {
+  let h = LOCK_WARNING_HANDLER.load(Ordering::Acquire);
+  if h.is_null() {
+    #[cfg(not(feature="no_std_support"))]
+    eprintln!("Waited {} attempts for lock {:#X}, possible deadlock", attempts, lock);
+  }
+  else {
+    // Only ever set from a fn(u64, u32) by set_lock_warning_handler()
+    let handler = unsafe { core::mem::transmute::<*mut (), fn(u64, u32)>(h) };
+    handler(lock, attempts);
+  }
+}
+
+/// How a thread waits while a ```SharedMutex``` is held by another thread
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub enum ContentionPolicy {
+  /// Retry with exponentially increasing delays between attempts.
+  #[default]
+  Backoff,
+  /// Retry continuously. Lowest latency, but burns CPU under heavy contention.
+  Spin,
+  /// Back off, then yield the thread to the scheduler once the delay reaches its maximum.
+  /// Behaves like ```Backoff``` when the "no_std_support" feature is enabled.
+  Yield,
+}
+
+impl ContentionPolicy {
+  fn from_u8(b:u8) -> ContentionPolicy {
+    match b {
+      1 => ContentionPolicy::Spin,
+      2 => ContentionPolicy::Yield,
+      _ => ContentionPolicy::Backoff,
+    }
+  }
+}
+
 /// A simple mutex that can be accessed globally. If "mirror" feature is enabled the mutex can be shared across partitions.
 #[derive(Debug, Default)]
 pub struct SharedMutex<T> {
@@ -18,6 +87,9 @@ pub struct SharedMutex<T> {
   
   my_ia: u64,
   my_d: u64,
+  
+  /// The ContentionPolicy used by this process when the mutex is contended
+  policy: AtomicU8,
 }
 
 impl<T> SharedMutex<T> {
@@ -29,6 +101,7 @@ impl<T> SharedMutex<T> {
       data_x: None,
       my_ia: 0,
       my_d: 0,
+      policy: AtomicU8::new(0),
     }
   }
 
@@ -41,6 +114,14 @@ impl<T> SharedMutex<T> {
     self.my_ia = (&self.is_acquired_x as *const AtomicBool) as u64;
     self.my_d = (&self.data_x as *const Option<UnsafeCell<T>>) as u64;
   }
+  
+  /// Set the underlying object to lock unless the mutex has already been set or mirrored, in 
+  /// which case ```t``` is dropped. Returns ```true``` if the object was set.
+  pub fn set_if_needed(&mut self, t:T) -> bool {
+    if self.is_initialized() { return false; }
+    self.set(t);
+    true
+  }
     
   /// Share the underlying locked object
   pub fn share(&self) -> (u64, u64) {
@@ -56,19 +137,112 @@ impl<T> SharedMutex<T> {
     self.my_d = r;
   }
 
+  /// Return a mutable reference to the underlying object without locking, or ```None``` if the
+  /// mutex was mirrored from another process or never set. Holding ```&mut self``` guarantees 
+  /// no guard exists in this process, but the caller must ensure that no mirror of this mutex
+  /// in another partition is in use.
+  pub fn get_mut(&mut self) -> Option<&mut T> {
+    self.data_x.as_mut().map(|cell| cell.get_mut())
+  }
+
+  /// Consume this mutex and return the underlying object, or ```None``` if the mutex was 
+  /// mirrored from another process or never set.
+  pub fn into_inner(self) -> Option<T> {
+    self.data_x.map(|cell| cell.into_inner())
+  }
+
   /// Lock this mutex
   fn do_lock(&self) -> bool {
     unsafe { return (*(self.my_ia as *const AtomicBool)).swap(true, Ordering::AcqRel); }
   }
   
-  /// Lock this mutex
+  /// Set how this process waits when the mutex is held by another thread. Defaults to 
+  /// ```ContentionPolicy::Backoff```.
+  pub fn set_contention_policy(&self, policy:ContentionPolicy) {
+    self.policy.store(policy as u8, Ordering::Relaxed);
+  }
+  
+  /// Get how this process waits when the mutex is held by another thread
+  pub fn contention_policy(&self) -> ContentionPolicy {
+    ContentionPolicy::from_u8(self.policy.load(Ordering::Relaxed))
+  }
+  
+  /// Wait before the next attempt to lock this mutex, doubling ```backoff``` each time
+  fn wait(policy:ContentionPolicy, backoff:&mut u32) {
+    if policy == ContentionPolicy::Spin {
+      spin_loop();
+      return;
+    }
+    #[cfg(not(feature="no_std_support"))]
+    if policy == ContentionPolicy::Yield && *backoff >= MAX_BACKOFF {
+      std::thread::yield_now();
+      return;
+    }
+    for _ in 0..*backoff {
+      spin_loop();
+    }
+    if *backoff < MAX_BACKOFF { *backoff *= 2; }
+  }
+  
+  /// Lock this mutex. Panics if the mutex has not been set or mirrored.
   pub fn lock(&self) -> SharedMutexGuard<'_, T> {
+    self.check_initialized();
+    let policy = self.contention_policy();
+    let mut backoff = 1;
+    #[cfg(feature="lock_warnings")]
+    let mut attempts:u32 = 0;
     while self.do_lock() {
-      spin_loop();
+      #[cfg(feature="lock_warnings")]
+      {
+        attempts = attempts.saturating_add(1);
+        if attempts == LOCK_WARNING_ATTEMPTS.load(Ordering::Relaxed) { warn_lock(self.my_ia, attempts); }
+      }
+      SharedMutex::<T>::wait(policy, &mut backoff);
     }
     SharedMutexGuard { mutex: &self }
   }
   
+  /// Lock this mutex, giving up and returning ```None``` after ```spins``` failed attempts. 
+  /// Waits between attempts according to the ```ContentionPolicy```. The limit is counted in 
+  /// attempts rather than time so that it also works with the "no_std_support" feature. Panics 
+  /// if the mutex has not been set or mirrored.
+  pub fn lock_timeout(&self, spins:u64) -> Option<SharedMutexGuard<'_, T>> {
+    self.check_initialized();
+    let policy = self.contention_policy();
+    let mut backoff = 1;
+    let mut attempts:u64 = 0;
+    while self.do_lock() {
+      if attempts >= spins { return None; }
+      attempts += 1;
+      SharedMutex::<T>::wait(policy, &mut backoff);
+    }
+    Some(SharedMutexGuard { mutex: self })
+  }
+  
+  /// Returns ```true``` if this mutex has been set or mirrored
+  pub(crate) fn is_initialized(&self) -> bool {
+    self.my_ia != 0
+  }
+  
+  fn check_initialized(&self) {
+    if !self.is_initialized() { panic!("sharedmutex has not been set or mirrored!"); }
+  }
+  
+  /// Lock this mutex if it is not already locked, making a single attempt. Returns ```None``` 
+  /// if the mutex is held by another thread. Panics if the mutex has not been set or mirrored.
+  pub fn try_lock(&self) -> Option<SharedMutexGuard<'_, T>> {
+    self.check_initialized();
+    if self.do_lock() { return None; }
+    Some(SharedMutexGuard { mutex: self })
+  }
+  
+  /// Like ```try_lock()```, but returns ```None``` instead of panicking if the mutex has not 
+  /// been set or mirrored. For reporting from places that must not panic, such as ```Debug```.
+  pub(crate) fn try_lock_if_initialized(&self) -> Option<SharedMutexGuard<'_, T>> {
+    if !self.is_initialized() { return None; }
+    self.try_lock()
+  }
+  
   /// Release the lock on this mutex
   fn release(&self) {
     unsafe { (*(self.my_ia as *const AtomicBool)).store(false, Ordering::Release); }
@@ -1,16 +1,107 @@
 extern crate alloc;
 use core::ops::Index;
+use core::ops::Deref;
+use core::ops::DerefMut;
 use core::mem;
+use core::mem::MaybeUninit;
+use core::ptr;
+use core::slice;
 use core::fmt;
 use core::fmt::Debug;
 
 #[cfg(feature="no_std_support")]
 use alloc::vec::Vec;
 
+/// The slots of a ```UsizeMap```
+enum Slots<T> {
+  /// Slots allocated by the global allocator
+  Vec(Vec<Option<T>>),
+  /// Slots stored in a fixed region of memory provided by the caller
+  Region {
+    /// The first slot in the region
+    ptr: *mut Option<T>,
+    /// The number of slots in use
+    len: usize,
+    /// The number of slots that fit in the region
+    cap: usize,
+  },
+}
+
+// The region is owned exclusively by the map, as a ```Vec``` owns its buffer.
+unsafe impl<T: Send> Send for Slots<T> {}
+unsafe impl<T: Sync> Sync for Slots<T> {}
+
+impl<T> Slots<T> {
+  fn push(&mut self, t:Option<T>) {
+    match self {
+      Slots::Vec(v) => v.push(t),
+      Slots::Region { ptr, len, cap } => {
+        if *len == *cap { panic!("UsizeMap region is full ({} slots)", cap); }
+        unsafe { ptr.add(*len).write(t); }
+        *len += 1;
+      },
+    }
+  }
+  
+  fn pop(&mut self) -> Option<Option<T>> {
+    match self {
+      Slots::Vec(v) => v.pop(),
+      Slots::Region { ptr, len, .. } => {
+        if *len == 0 { return None; }
+        *len -= 1;
+        Some(unsafe { ptr.add(*len).read() })
+      },
+    }
+  }
+  
+  fn shrink_to_fit(&mut self) {
+    if let Slots::Vec(v) = self { v.shrink_to_fit(); }
+  }
+  
+  /// Remove every slot, leaving the storage empty but keeping its region, if any.
+  fn take_all(&mut self) -> Vec<Option<T>> {
+    match self {
+      Slots::Vec(v) => mem::take(v),
+      Slots::Region { ptr, len, .. } => {
+        let n = mem::replace(len, 0);
+        (0..n).map(|i| unsafe { ptr.add(i).read() }).collect()
+      },
+    }
+  }
+}
+
+impl<T> Deref for Slots<T> {
+  type Target = [Option<T>];
+  
+  fn deref(&self) -> &[Option<T>] {
+    match self {
+      Slots::Vec(v) => v,
+      Slots::Region { ptr, len, .. } => unsafe { slice::from_raw_parts(*ptr, *len) },
+    }
+  }
+}
+
+impl<T> DerefMut for Slots<T> {
+  fn deref_mut(&mut self) -> &mut [Option<T>] {
+    match self {
+      Slots::Vec(v) => v,
+      Slots::Region { ptr, len, .. } => unsafe { slice::from_raw_parts_mut(*ptr, *len) },
+    }
+  }
+}
+
+impl<T> Drop for Slots<T> {
+  fn drop(&mut self) {
+    if let Slots::Region { ptr, len, .. } = self {
+      unsafe { ptr::drop_in_place(ptr::slice_from_raw_parts_mut(*ptr, *len)); }
+    }
+  }
+}
+
 /// A map of type ```<usize, T>``` where the keys are generated and reused by the map.
 pub struct UsizeMap<T> {
   /// The list of objects contained in this map
-  data: Vec<Option<T>>,
+  data: Slots<T>,
   /// The list of empty (reusable) keys
   empty: Vec<usize>,
 }
@@ -19,11 +110,43 @@ impl<T: Debug> UsizeMap<T> {
   /// Return a new (empty) ```UsizeMap```.
   pub fn new() -> UsizeMap<T> {
     UsizeMap {
-      data: Vec::new(),
+      data: Slots::Vec(Vec::new()),
+      empty: Vec::new(),
+    }
+  }
+  
+  /// Return a new (empty) ```UsizeMap``` with space pre-allocated for at least ```capacity``` 
+  /// objects, so that the map does not reallocate until it grows past that size.
+  pub fn with_capacity(capacity:usize) -> UsizeMap<T> {
+    UsizeMap {
+      data: Slots::Vec(Vec::with_capacity(capacity)),
       empty: Vec::new(),
     }
   }
   
+  /// Return a new (empty) ```UsizeMap``` that stores its objects in the given region of memory
+  /// instead of allocating space for them, such as a static buffer or memory shared with 
+  /// another process. The region is aligned as needed, and the map holds as many objects as 
+  /// fit in the rest of it (see ```region_size()```). Inserting into a full map panics.
+  ///
+  /// Only the objects themselves are stored in the region. The list of reusable keys, and any
+  /// memory the objects allocate, still come from the global allocator.
+  pub fn in_region(region:&'static mut [MaybeUninit<u8>]) -> UsizeMap<T> {
+    let size = mem::size_of::<Option<T>>();
+    let offset = region.as_mut_ptr().align_offset(mem::align_of::<Option<T>>()).min(region.len());
+    let cap = (region.len() - offset).checked_div(size).unwrap_or(usize::MAX);
+    UsizeMap {
+      data: Slots::Region { ptr: unsafe { region.as_mut_ptr().add(offset) } as *mut Option<T>, len: 0, cap },
+      empty: Vec::new(),
+    }
+  }
+  
+  /// Return the number of bytes a region passed to ```in_region()``` needs in order to hold 
+  /// ```capacity``` objects, however it is aligned.
+  pub const fn region_size(capacity:usize) -> usize {
+    capacity * mem::size_of::<Option<T>>() + mem::align_of::<Option<T>>() - 1
+  }
+  
   /// Add an object to this map and return a key (```usize```) for it.
   pub fn insert(&mut self, t:T) -> usize {
     if self.empty.len() > 0 {
@@ -48,17 +171,80 @@ impl<T: Debug> UsizeMap<T> {
     None
   } 
   
+  /// Return mutable references to the stored values with the given keys, or ```None``` if any
+  /// key is missing or repeated.
+  pub fn get_disjoint_mut<const N: usize>(&mut self, keys:[usize; N]) -> Option<[&mut T; N]> {
+    for (n, i) in keys.iter().enumerate() {
+      if keys[..n].contains(i) || self.get_mut(*i).is_none() { return None; }
+    }
+    let data = self.data.as_mut_ptr();
+    // The keys are distinct and refer to occupied slots, so the references do not alias.
+    Some(keys.map(|i| unsafe { (*data.add(i)).as_mut().unwrap() }))
+  }
+  
+  /// Return an iterator over the keys and mutable references to the values stored in this map.
+  pub fn iter_mut(&mut self) -> impl Iterator<Item = (usize, &mut T)> {
+    self.data.iter_mut().enumerate().filter_map(|(i, x)| x.as_mut().map(|t| (i, t)))
+  }
+  
   /// Remove the stored value with the given key.
   pub fn remove(&mut self, i:usize) -> Option<T> {
     self.empty.push(i);
     mem::replace(&mut self.data[i], None)
   }
   
+  /// Keep only the values for which ```f``` returns ```true```, dropping the rest and making their
+  /// keys available for reuse.
+  pub fn retain<F: FnMut(usize, &mut T) -> bool>(&mut self, mut f:F) {
+    for (i, x) in self.data.iter_mut().enumerate() {
+      if let Some(t) = x {
+        if !f(i, t) {
+          *x = None;
+          self.empty.push(i);
+        }
+      }
+    }
+  }
+  
+  /// Remove all values from this map, returning an iterator over their keys and values. The 
+  /// map is left empty, and keys are assigned from zero again.
+  pub fn drain(&mut self) -> impl Iterator<Item = (usize, T)> {
+    self.empty.clear();
+    self.data.take_all().into_iter().enumerate().filter_map(|(i, x)| x.map(|t| (i, t)))
+  }
+  
   /// Return the number of key/value pairs contained in this map.
   pub fn len(&self) -> usize {
     self.data.len() - self.empty.len()
   }
 
+  /// Return the number of slots (used or reusable) allocated by this map.
+  pub fn capacity(&self) -> usize {
+    self.data.len()
+  }
+
+  /// Release the empty slots at the end of this map, and any memory no longer needed to hold the
+  /// remaining slots. Returns the number of slots released. Keys in use are unchanged.
+  pub fn shrink_to_fit(&mut self) -> usize {
+    let n = self.data.len();
+    while let Some(None) = self.data.last() {
+      self.data.pop();
+    }
+    let len = self.data.len();
+    self.empty.retain(|i| *i < len);
+    self.data.shrink_to_fit();
+    self.empty.shrink_to_fit();
+    n - len
+  }
+
+  /// Return the ratio of used slots to allocated slots, from ```0.0``` to ```1.0```. A low
+  /// value indicates the map has become sparse. An empty map returns ```1.0```.
+  pub fn fill_ratio(&self) -> f64 {
+    let n = self.data.len();
+    if n == 0 { return 1.0; }
+    self.len() as f64 / n as f64
+  }
+
   /// List the keys to the data in this map
   pub fn keys(&self) -> Vec<usize> {
     let mut v = Vec::new();
@@ -89,3 +275,22 @@ impl<T: core::fmt::Debug> fmt::Debug for UsizeMap<T> {
   }
 }
 
+
+/// Collect values into a new map. The values are assigned keys ```0, 1, 2...``` in iteration 
+/// order.
+impl<T: Debug> FromIterator<T> for UsizeMap<T> {
+  fn from_iter<I: IntoIterator<Item = T>>(iter:I) -> UsizeMap<T> {
+    let mut m = UsizeMap::new();
+    m.extend(iter);
+    m
+  }
+}
+
+/// Add values to the map as for ```insert()```, filling reusable keys first.
+impl<T: Debug> Extend<T> for UsizeMap<T> {
+  fn extend<I: IntoIterator<Item = T>>(&mut self, iter:I) {
+    for t in iter {
+      self.insert(t);
+    }
+  }
+}
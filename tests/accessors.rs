@@ -0,0 +1,95 @@
+mod common;
+
+use std::fmt::Debug;
+use std::panic;
+use std::panic::AssertUnwindSafe;
+use ndata::dataobject::DataObject;
+use ndata::dataarray::DataArray;
+use ndata::databytes::DataBytes;
+use ndata::ndataerror::NDataError;
+
+/// Assert that ```get``` returns a value exactly when ```try_get``` does, and that it is the same value.
+fn assert_parity<T: PartialEq + Debug>(what:&str, get:impl FnOnce() -> T, try_get:impl FnOnce() -> Result<T, NDataError>) {
+  let hook = panic::take_hook();
+  panic::set_hook(Box::new(|_| {}));
+  let a = panic::catch_unwind(AssertUnwindSafe(get)).ok();
+  panic::set_hook(hook);
+  let b = try_get().ok();
+  assert_eq!(a, b, "{}", what);
+}
+
+fn sample_object() -> DataObject {
+  let mut o = DataObject::new();
+  o.put_int("int", 7);
+  o.put_float("float", 1.5);
+  o.put_string("string", "seven");
+  o.put_boolean("bool", true);
+  o.put_null("null");
+  o.put_object("object", DataObject::new());
+  o.put_array("array", DataArray::new());
+  o.put_bytes("bytes", DataBytes::from_bytes(&vec![1, 2, 3]));
+  o
+}
+
+#[test]
+fn object_try_getters_match_getters() {
+  let _guard = common::setup();
+  let o = sample_object();
+  let keys = ["int", "float", "string", "bool", "null", "object", "array", "bytes", "missing"];
+  for key in keys {
+    assert_parity(key, || o.get_int(key), || o.try_get_int(key));
+    assert_parity(key, || o.get_float(key), || o.try_get_float(key));
+    assert_parity(key, || o.get_string(key), || o.try_get_string(key));
+    assert_parity(key, || o.get_boolean(key), || o.try_get_boolean(key));
+    assert_parity(key, || o.get_object(key).data_ref, || o.try_get_object(key).map(|x| x.data_ref));
+    assert_parity(key, || o.get_array(key).data_ref, || o.try_get_array(key).map(|x| x.data_ref));
+    assert_parity(key, || o.get_bytes(key).data_ref, || o.try_get_bytes(key).map(|x| x.data_ref));
+  }
+}
+
+#[test]
+fn array_try_getters_match_getters() {
+  let _guard = common::setup();
+  let o = sample_object();
+  let mut a = DataArray::new();
+  for key in o.clone().keys() {
+    a.push_property(o.get_property(&key));
+  }
+  for i in 0..a.len()+1 {
+    let what = format!("index {}", i);
+    assert_parity(&what, || a.get_int(i), || a.try_get_int(i));
+    assert_parity(&what, || a.get_float(i), || a.try_get_float(i));
+    assert_parity(&what, || a.get_string(i), || a.try_get_string(i));
+    assert_parity(&what, || a.get_boolean(i), || a.try_get_boolean(i));
+    assert_parity(&what, || a.get_object(i).data_ref, || a.try_get_object(i).map(|x| x.data_ref));
+    assert_parity(&what, || a.get_array(i).data_ref, || a.try_get_array(i).map(|x| x.data_ref));
+    assert_parity(&what, || a.get_bytes(i).data_ref, || a.try_get_bytes(i).map(|x| x.data_ref));
+  }
+}
+
+#[test]
+fn try_getters_report_the_found_type() {
+  let _guard = common::setup();
+  let o = sample_object();
+  match o.try_get_int("string") {
+    Err(NDataError::WrongDataType { expected, found }) => assert_eq!((expected, found), ("int", "string")),
+    x => panic!("unexpected result {:?}", x),
+  }
+  assert!(o.try_get_int("missing").is_err());
+}
+
+#[test]
+fn get_path_reports_invalid_array_index() {
+  let _guard = common::setup();
+  let mut o = sample_object();
+  let mut a = DataArray::new();
+  a.push_int(3);
+  o.put_array("list", a);
+  assert_eq!(o.get_path("list.0").unwrap().int(), 3);
+  let error = |e:NDataError| match e {
+    NDataError::Path { path, error } => (path, *error),
+    e => panic!("Expected a path error but found {:?}", e),
+  };
+  assert_eq!(error(o.get_path("list.first").unwrap_err()), ("list.first".to_string(), NDataError::InvalidIndex("first".to_string())));
+  assert_eq!(error(o.get_path("list.1").unwrap_err()), ("list.1".to_string(), NDataError::IndexOutOfBounds { index: 1, len: 1 }));
+}
@@ -0,0 +1,44 @@
+#![cfg(feature="bytes_json")]
+
+mod common;
+
+use ndata::dataobject::DataObject;
+use ndata::dataarray::DataArray;
+use ndata::databytes::DataBytes;
+
+/// Every byte value, so that padding and all base64 characters are exercised
+fn sample_bytes() -> Vec<u8> {
+  (0..=255).chain([0, 1]).collect()
+}
+
+#[test]
+fn bytes_round_trip_through_json() {
+  let _guard = common::setup();
+  let bytes = sample_bytes();
+  let mut o = DataObject::new();
+  o.put_bytes("bytes", DataBytes::from_bytes(&bytes));
+  o.put_bytes("empty", DataBytes::new());
+  let mut a = DataArray::new();
+  a.push_bytes(DataBytes::from_bytes(&bytes[..2].to_vec()));
+  o.put_array("list", a);
+  let s = o.to_string();
+  let o = DataObject::from_string(&s);
+  assert!(o.get_property("bytes").is_bytes());
+  assert_eq!(o.get_bytes("bytes").get_data(), bytes);
+  assert_eq!(o.get_bytes("empty").get_data(), Vec::<u8>::new());
+  assert_eq!(o.get_array("list").get_bytes(0).get_data(), bytes[..2].to_vec());
+}
+
+#[cfg(feature="serde_support")]
+#[test]
+fn standalone_bytes_serialize_as_tagged_json() {
+  let _guard = common::setup();
+  let bytes = sample_bytes();
+  let mut o = DataObject::new();
+  o.put_bytes("bytes", DataBytes::from_bytes(&bytes));
+  let s = serde_json::to_string(&DataBytes::from_bytes(&bytes)).unwrap();
+  assert_eq!(s, o.to_json()["bytes"].to_string());
+  let b:DataBytes = serde_json::from_str(&s).unwrap();
+  assert_eq!(b.get_data(), bytes);
+  assert!(serde_json::from_str::<DataBytes>("\"00ff\"").is_err());
+}
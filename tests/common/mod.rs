@@ -0,0 +1,46 @@
+use std::sync::Mutex;
+use std::sync::MutexGuard;
+
+/// Serializes the tests in a test binary, since they all share the global heaps
+static LOCK:Mutex<()> = Mutex::new(());
+
+/// Initializes global storage when ```ndata::init_once()``` is not available
+#[cfg(feature="no_std_support")]
+static INIT:std::sync::Once = std::sync::Once::new();
+
+/// Initialize global storage of data if needed, and hold the returned guard for the duration of
+/// the test so that no other test in the same binary runs at the same time.
+#[allow(dead_code)]
+pub fn setup() -> MutexGuard<'static, ()> {
+  let guard = LOCK.lock().unwrap_or_else(|e| e.into_inner());
+  #[cfg(not(feature="no_std_support"))]
+  ndata::init_once();
+  #[cfg(feature="no_std_support")]
+  INIT.call_once(|| { ndata::init(); });
+  guard
+}
+
+/// Initializes global storage for ```setup_with_globals()```
+static INIT_WITH_GLOBALS:std::sync::Once = std::sync::Once::new();
+
+/// Like ```setup()```, but initialize global storage with ```ndata::init_with_globals()```. Do 
+/// not combine with ```setup()``` in the same test binary.
+#[allow(dead_code)]
+pub fn setup_with_globals() -> MutexGuard<'static, ()> {
+  let guard = LOCK.lock().unwrap_or_else(|e| e.into_inner());
+  INIT_WITH_GLOBALS.call_once(|| { ndata::init_with_globals(); });
+  guard
+}
+
+/// Returns ```true``` if the object with the given data_ref is still on the heap.
+#[allow(dead_code)]
+pub fn object_exists(data_ref:usize) -> bool {
+  ndata::dataobject::oheap().lock().try_get(data_ref).is_some()
+}
+
+/// Collect garbage, then return the number of objects and arrays on the heap.
+#[allow(dead_code)]
+pub fn heap_counts() -> (usize, usize) {
+  ndata::gc();
+  (ndata::dataobject::oheap().lock().keys().len(), ndata::dataarray::aheap().lock().keys().len())
+}
@@ -0,0 +1,67 @@
+mod common;
+
+use ndata::data::Data;
+use ndata::dataobject::DataObject;
+use ndata::dataarray::DataArray;
+use ndata::databytes::DataBytes;
+
+/// Build a new object holding nested objects, arrays and bytes, with the given innermost value.
+fn build(leaf:i64) -> DataObject {
+  let mut inner = DataObject::new();
+  inner.put_int("leaf", leaf);
+  inner.put_bytes("bytes", DataBytes::from_bytes(&vec![1, 2, 3]));
+  let mut list = DataArray::new();
+  list.push_string("a");
+  list.push_object(inner);
+  let mut nested = DataArray::new();
+  nested.push_array(DataArray::new());
+  list.push_array(nested);
+  let mut o = DataObject::new();
+  o.put_array("list", list);
+  o.put_float("float", 0.5);
+  o
+}
+
+fn deep_equals(a:&DataObject, b:&DataObject) -> bool {
+  Data::deep_equals(&Data::DObject(a.data_ref), &Data::DObject(b.data_ref))
+}
+
+#[test]
+fn separately_built_structures_are_deep_equal() {
+  let _guard = common::setup();
+  let a = build(1);
+  let b = build(1);
+  assert_ne!(a.data_ref, b.data_ref);
+  assert!(deep_equals(&a, &b));
+  assert!(!Data::equals(Data::DObject(a.data_ref), Data::DObject(b.data_ref)));
+  assert!(!deep_equals(&a, &build(2)));
+}
+
+#[test]
+fn differences_in_nested_values_are_found() {
+  let _guard = common::setup();
+  let a = build(1);
+  let b = build(1);
+  b.get_array("list").get_array(2).push_int(0);
+  assert!(!deep_equals(&a, &b));
+  let c = build(1);
+  let mut list = c.get_array("list");
+  let first = list.pop_front().unwrap();
+  list.push_property(first.data());
+  assert!(!deep_equals(&a, &c));
+  let d = build(1);
+  d.get_array("list").get_object(1).get_bytes("bytes").set_data(&vec![1, 2, 4]);
+  assert!(!deep_equals(&a, &d));
+}
+
+#[test]
+fn cyclic_structures_are_compared() {
+  let _guard = common::setup();
+  let mut a = build(1);
+  let mut b = build(1);
+  a.put_object("self", a.clone());
+  b.put_object("self", b.clone());
+  assert!(deep_equals(&a, &b));
+  a.remove_property("self");
+  b.remove_property("self");
+}
@@ -0,0 +1,46 @@
+mod common;
+
+use ndata::dataobject::DataObject;
+use ndata::dataarray::DataArray;
+use ndata::databytes::DataBytes;
+
+#[test]
+fn default_object_is_a_new_instance() {
+  let _guard = common::setup();
+  let mut held = DataObject::new();
+  held.put_int("a", 1);
+  let mut x = DataObject::default();
+  let y = DataObject::default();
+  assert_ne!(x.data_ref, held.data_ref);
+  assert_ne!(x.data_ref, y.data_ref);
+  x.put_int("b", 2);
+  assert_eq!(held.clone().keys(), vec!["a".to_string()]);
+  assert!(!y.has("b"));
+}
+
+#[test]
+fn default_array_is_a_new_instance() {
+  let _guard = common::setup();
+  let mut held = DataArray::new();
+  held.push_int(1);
+  let mut x = DataArray::default();
+  let y = DataArray::default();
+  assert_ne!(x.data_ref, held.data_ref);
+  assert_ne!(x.data_ref, y.data_ref);
+  x.push_int(2);
+  assert_eq!(held.len(), 1);
+  assert_eq!(y.len(), 0);
+}
+
+#[test]
+fn default_bytes_is_a_new_instance() {
+  let _guard = common::setup();
+  let held = DataBytes::from_bytes(&vec![1]);
+  let x = DataBytes::default();
+  let y = DataBytes::default();
+  assert_ne!(x.data_ref, held.data_ref);
+  assert_ne!(x.data_ref, y.data_ref);
+  x.write(&[2, 3]);
+  assert_eq!(held.get_data(), vec![1]);
+  assert_eq!(y.get_data(), Vec::<u8>::new());
+}
@@ -0,0 +1,52 @@
+#![cfg(not(feature="serde_support"))]
+
+mod common;
+
+use ndata::json_util::*;
+
+const JSON:&str = r#"{"a":{"first":[1]},"a":{"last":[2]}}"#;
+
+/// Parse ```JSON``` with the given policy and return the keys of the value kept for "a", or
+/// the error. Checks that the discarded duplicate and its array were released.
+fn parse_with(policy:DuplicateKeyPolicy) -> Result<Vec<String>, ParseError> {
+  set_duplicate_key_policy(policy);
+  let before = common::heap_counts();
+  let result = try_object_from_string(JSON);
+  set_duplicate_key_policy(DuplicateKeyPolicy::LastWins);
+  let keys = result.as_ref().map(|o| o.get_object("a").keys()).map_err(|e| e.error.clone());
+  let after = common::heap_counts();
+  match result {
+    // The outer object and the kept value with its array
+    Ok(_) => assert_eq!(after, (before.0 + 2, before.1 + 1)),
+    Err(_) => assert_eq!(after, before),
+  }
+  keys
+}
+
+#[test]
+fn last_wins() {
+  let _guard = common::setup();
+  assert_eq!(parse_with(DuplicateKeyPolicy::LastWins), Ok(vec!["last".to_string()]));
+}
+
+#[test]
+fn first_wins() {
+  let _guard = common::setup();
+  assert_eq!(parse_with(DuplicateKeyPolicy::FirstWins), Ok(vec!["first".to_string()]));
+}
+
+#[test]
+fn error() {
+  let _guard = common::setup();
+  assert_eq!(parse_with(DuplicateKeyPolicy::Error), Err(ParseError::DuplicateKey("a".to_string())));
+}
+
+#[test]
+fn strict_parsing_rejects_duplicate_keys() {
+  let _guard = common::setup();
+  let e = object_from_string_strict(r#"{"a":1,"a":2}"#).unwrap_err();
+  assert_eq!(e.error, ParseError::DuplicateKey("a".to_string()));
+  let e = array_from_string_strict(r#"[{"b":{"a":1,"a":2}}]"#).unwrap_err();
+  assert_eq!(e.error, ParseError::DuplicateKey("a".to_string()));
+  assert_eq!(object_from_string_strict(r#"{"a":1,"b":{"a":2}}"#).unwrap().get_object("b").get_int("a"), 2);
+}
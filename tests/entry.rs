@@ -0,0 +1,38 @@
+mod common;
+
+use ndata::data::Data;
+use ndata::dataobject::DataObject;
+
+#[test]
+fn and_modify_changes_present_values_in_place() {
+  let _guard = common::setup();
+  let mut o = DataObject::new();
+  o.put_int("n", 1);
+  o.entry("n").and_modify(|d| *d = Data::DInt(d.int() + 1));
+  assert_eq!(o.get_int("n"), 2);
+  let mut called = false;
+  let d = o.entry("m").and_modify(|_d| called = true).or_insert_with(|| Data::DInt(7));
+  assert!(!called);
+  assert_eq!(d.int(), 7);
+  assert_eq!(o.get_int("m"), 7);
+}
+
+#[test]
+fn and_modify_counts_references_to_replaced_containers() {
+  let _guard = common::setup();
+  let mut o = DataObject::new();
+  let old = DataObject::new();
+  let old_ref = old.data_ref;
+  o.put_object("x", old);
+  let new = DataObject::new();
+  let new_ref = new.data_ref;
+  o.entry("x").and_modify(|d| *d = Data::DObject(new_ref));
+  drop(new);
+  ndata::gc();
+  assert!(!common::object_exists(old_ref));
+  assert!(common::object_exists(new_ref));
+  assert_eq!(o.get_object("x").data_ref, new_ref);
+  o.remove_property("x");
+  ndata::gc();
+  assert!(!common::object_exists(new_ref));
+}
@@ -0,0 +1,26 @@
+mod common;
+
+use ndata::dataobject::DataObject;
+use ndata::dataobject::oheap;
+
+/// Create and drop the given number of objects, then return the number of objects on the heap
+/// without collecting garbage.
+fn drop_objects(n:usize) -> usize {
+  for _ in 0..n {
+    let _o = DataObject::new();
+  }
+  oheap().lock().keys().len()
+}
+
+#[test]
+fn threshold_collects_without_gc() {
+  let _guard = common::setup();
+  ndata::set_gc_threshold(0);
+  let (base, _) = common::heap_counts();
+  assert_eq!(drop_objects(10), base + 10);
+  ndata::gc();
+  ndata::set_gc_threshold(4);
+  let count = drop_objects(10);
+  ndata::set_gc_threshold(0);
+  assert!(count <= base + 4, "{} objects on the heap, expected at most {}", count, base + 4);
+}
@@ -0,0 +1,36 @@
+#![cfg(not(feature="no_std_support"))]
+
+mod common;
+
+use std::io;
+use std::io::Read;
+use ndata::databytes::DataBytes;
+
+#[test]
+fn io_copy_round_trip_after_close_write() {
+  let _guard = common::setup();
+  let input:Vec<u8> = (0..100_000u32).map(|i| (i % 251) as u8).collect();
+  let mut b = DataBytes::new();
+  assert_eq!(io::copy(&mut input.as_slice(), &mut b).unwrap(), input.len() as u64);
+  let mut buf = [0u8; 10];
+  assert_eq!(Read::read(&mut b, &mut buf).unwrap(), 10);
+  b.close_write();
+  let mut output = buf.to_vec();
+  assert_eq!(io::copy(&mut b, &mut output).unwrap(), input.len() as u64 - 10);
+  assert_eq!(output, input);
+  assert!(!b.is_read_open());
+  assert_eq!(Read::read(&mut b, &mut buf).unwrap(), 0);
+}
+
+#[test]
+fn read_would_block_while_open_for_writing() {
+  let _guard = common::setup();
+  let mut b = DataBytes::new();
+  b.write(b"abc");
+  let mut output = Vec::new();
+  let e = io::copy(&mut b, &mut output).unwrap_err();
+  assert_eq!(e.kind(), io::ErrorKind::WouldBlock);
+  assert_eq!(output, b"abc");
+  b.close_write();
+  assert_eq!(io::copy(&mut b, &mut output).unwrap(), 0);
+}
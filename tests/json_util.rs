@@ -0,0 +1,118 @@
+#![cfg(not(feature="serde_support"))]
+
+mod common;
+
+use std::io;
+use std::io::Read;
+
+use ndata::dataobject::DataObject;
+use ndata::dataarray::DataArray;
+use ndata::json_util::*;
+use ndata::ndataerror::NDataError;
+
+/// Build an object with a nested object and array, setting the keys in the given order.
+fn object_in_order(keys:&[&str]) -> DataObject {
+  let mut o = DataObject::new();
+  for key in keys {
+    let mut inner = DataObject::new();
+    for k in keys.iter().rev() {
+      inner.put_int(k, k.len() as i64);
+    }
+    let mut a = DataArray::new();
+    a.push_object(inner);
+    o.put_array(key, a);
+  }
+  o
+}
+
+#[test]
+fn sorted_output_does_not_depend_on_insertion_order() {
+  let _guard = common::setup();
+  let a = object_in_order(&["b", "a", "ccc", "dd"]);
+  let b = object_in_order(&["dd", "ccc", "a", "b"]);
+  let s = object_to_string_sorted(a.clone());
+  assert_eq!(s, object_to_string_sorted(b.clone()));
+  assert!(s.starts_with(r#"{"a":[{"a":1,"b":1,"ccc":3,"dd":2}],"b":"#));
+  assert_eq!(object_to_string_pretty(a, 2, true), object_to_string_pretty(b, 2, true));
+}
+
+/// A reader that returns at most ```chunk``` bytes per call
+struct Chunked<'a> {
+  data: &'a [u8],
+  chunk: usize,
+}
+
+impl Read for Chunked<'_> {
+  fn read(&mut self, buf:&mut [u8]) -> io::Result<usize> {
+    let n = self.chunk.min(buf.len()).min(self.data.len());
+    buf[..n].copy_from_slice(&self.data[..n]);
+    self.data = &self.data[n..];
+    Ok(n)
+  }
+}
+
+#[test]
+fn reader_parses_a_large_array_in_chunks() {
+  let _guard = common::setup();
+  let n = 100_000;
+  let items:Vec<String> = (0..n).map(|i| format!("{{\"i\":{},\"s\":\"x{}\"}}", i, i)).collect();
+  let json = format!("[{}]", items.join(","));
+  assert!(json.len() > 2_000_000);
+  let r = Chunked { data: json.as_bytes(), chunk: 4093 };
+  let a = array_from_reader(io::BufReader::with_capacity(1024, r).bytes().map(|b| b.unwrap())).unwrap();
+  assert_eq!(a.len(), n);
+  assert_eq!(a.get_object(0).get_int("i"), 0);
+  assert_eq!(a.get_object(n - 1).get_string("s"), format!("x{}", n - 1));
+  let e = array_from_reader(json.as_bytes()[..json.len() - 1].iter().copied()).unwrap_err();
+  assert_eq!(e.error, ParseError::UnexpectedEnd);
+  assert_eq!(e.offset, json.len() - 1);
+}
+
+#[test]
+fn non_finite_policy_applies_only_to_write_functions() {
+  let _guard = common::setup();
+  let mut o = DataObject::new();
+  o.put_float("inf", f64::INFINITY);
+  let mut a = DataArray::new();
+  a.push_float(f64::NAN);
+  let mut s = String::new();
+  write_object(&mut s, &o).unwrap();
+  assert_eq!(s, r#"{"inf":null}"#);
+  set_non_finite_policy(NonFinitePolicy::Error);
+  let written = (write_object(&mut String::new(), &o), write_array(&mut String::new(), &a));
+  let strings = (o.to_string(), array_to_string(a.clone()), object_to_string_pretty(o.clone(), 2, true));
+  set_non_finite_policy(NonFinitePolicy::Null);
+  assert!(written.0.is_err());
+  assert!(written.1.is_err());
+  assert_eq!(strings, (r#"{"inf":null}"#.to_string(), "[null]".to_string(), "{\n  \"inf\": null\n}".to_string()));
+}
+
+#[test]
+#[cfg(not(feature="no_std_support"))]
+fn non_finite_policy_applies_to_io_writers() {
+  let _guard = common::setup();
+  let mut o = DataObject::new();
+  o.put_float("inf", f64::INFINITY);
+  let mut v = Vec::new();
+  write_object_io(&mut v, &o).unwrap();
+  assert_eq!(v, br#"{"inf":null}"#);
+  set_non_finite_policy(NonFinitePolicy::Error);
+  let written = write_object_io(&mut Vec::new(), &o);
+  set_non_finite_policy(NonFinitePolicy::Null);
+  assert!(written.is_err());
+}
+
+/// Parse an object, converting the error with ```?```
+fn parse(s:&str) -> Result<DataObject, NDataError> {
+  Ok(try_object_from_string(s)?)
+}
+
+#[test]
+fn parse_errors_keep_their_offset() {
+  let _guard = common::setup();
+  let e = parse(r#"{"a":1,"b":x}"#).unwrap_err();
+  assert_eq!(e, NDataError::Parse { error: ParseError::UnexpectedChar('x'), offset: Some(12) });
+  assert_eq!(e.to_string(), "Error parsing JSON: Unexpected character 'x' at byte 12");
+  let e = NDataError::from(ParseError::UnexpectedEnd);
+  assert_eq!(e, NDataError::Parse { error: ParseError::UnexpectedEnd, offset: None });
+}
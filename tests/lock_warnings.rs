@@ -0,0 +1,29 @@
+#![cfg(feature="lock_warnings")]
+
+use std::sync::atomic::AtomicU32;
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::time::Duration;
+use ndata::sharedmutex::*;
+
+static WARNINGS:AtomicU32 = AtomicU32::new(0);
+
+fn count_warning(_lock:u64, _attempts:u32) {
+  WARNINGS.fetch_add(1, Ordering::SeqCst);
+}
+
+#[test]
+fn handler_is_called_for_long_waits() {
+  set_lock_warning_attempts(1000);
+  set_lock_warning_handler(count_warning);
+  let m:&'static mut SharedMutex<u32> = Box::leak(Box::new(SharedMutex::new()));
+  m.set(0);
+  let m:&'static SharedMutex<u32> = m;
+  let guard = m.lock();
+  let t = thread::spawn(move || { *m.lock() += 1; });
+  thread::sleep(Duration::from_millis(50));
+  drop(guard);
+  t.join().unwrap();
+  assert_eq!(*m.lock(), 1);
+  assert!(WARNINGS.load(Ordering::SeqCst) > 0);
+}
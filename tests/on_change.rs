@@ -0,0 +1,110 @@
+mod common;
+
+use std::sync::Mutex;
+use ndata::data::Data;
+use ndata::dataobject::DataObject;
+
+static SEEN:Mutex<Vec<(String, String)>> = Mutex::new(Vec::new());
+
+fn record(key:&str, data:&Data) {
+  SEEN.lock().unwrap().push((key.to_string(), format!("{:?}", data)));
+}
+
+/// Returns the changes recorded since the last call, sorted by key.
+fn seen() -> Vec<(String, String)> {
+  let mut v = std::mem::take(&mut *SEEN.lock().unwrap());
+  v.sort();
+  v
+}
+
+fn pair(key:&str, data:&str) -> (String, String) {
+  (key.to_string(), data.to_string())
+}
+
+fn observed(keys:&[&str]) -> DataObject {
+  let o = DataObject::new();
+  for key in keys {
+    o.on_change(key, record);
+  }
+  o
+}
+
+#[test]
+fn rename_key_reports_both_keys() {
+  let _guard = common::setup();
+  let mut o = observed(&["a", "b"]);
+  o.put_int("a", 1);
+  seen();
+  assert!(o.rename_key("a", "b"));
+  assert_eq!(seen(), vec![pair("a", "DNull"), pair("b", "DInt(1)")]);
+}
+
+#[test]
+fn rename_keys_reports_renamed_keys() {
+  let _guard = common::setup();
+  let mut o = observed(&["a", "A", "z"]);
+  o.put_int("a", 1);
+  o.put_int("z", 2);
+  seen();
+  o.rename_keys(|k| if k == "a" { "A".to_string() } else { k.to_string() });
+  assert_eq!(seen(), vec![pair("A", "DInt(1)"), pair("a", "DNull")]);
+}
+
+#[test]
+fn checked_increment_int_reports_the_new_value() {
+  let _guard = common::setup();
+  let mut o = observed(&["n"]);
+  o.put_int("n", 1);
+  seen();
+  assert_eq!(o.checked_increment_int("n", 2), Some(3));
+  assert_eq!(o.checked_increment_int("n", i64::MAX), None);
+  assert_eq!(seen(), vec![pair("n", "DInt(3)")]);
+}
+
+#[test]
+fn merge_into_reports_merged_keys() {
+  let _guard = common::setup();
+  let mut o = observed(&["a", "b"]);
+  let mut shared = DataObject::new();
+  shared.put_int("a", 1);
+  let keep = shared.clone();
+  o.merge_into(shared);
+  assert_eq!(seen(), vec![pair("a", "DInt(1)")]);
+  drop(keep);
+  let mut moved = DataObject::new();
+  moved.put_int("b", 2);
+  o.merge_into(moved);
+  assert_eq!(seen(), vec![pair("b", "DInt(2)")]);
+}
+
+#[test]
+fn entry_inserts_are_reported() {
+  let _guard = common::setup();
+  let mut o = observed(&["a", "b", "c"]);
+  let inner = o.entry("a").or_insert_object();
+  o.entry("b").or_insert_array();
+  o.entry("c").or_insert_with(|| Data::DInt(3));
+  let changes = seen();
+  assert_eq!(changes.len(), 3);
+  assert_eq!(changes[0], pair("a", &format!("{:?}", Data::DObject(inner.data_ref))));
+  assert!(changes[1].0 == "b" && changes[1].1.starts_with("DArray"));
+  assert_eq!(changes[2], pair("c", "DInt(3)"));
+  o.entry("a").or_insert_object();
+  o.entry("b").or_insert_array();
+  o.entry("c").or_insert_with(|| Data::DInt(4));
+  assert_eq!(seen(), vec![]);
+}
+
+#[test]
+fn deep_copy_into_reports_removed_keys() {
+  let _guard = common::setup();
+  let mut target = observed(&["old", "kept"]);
+  target.put_int("old", 1);
+  target.put_int("kept", 2);
+  seen();
+  let mut source = DataObject::new();
+  source.put_int("kept", 3);
+  source.deep_copy_into(&mut target);
+  assert_eq!(seen(), vec![pair("kept", "DInt(3)"), pair("old", "DNull")]);
+  assert!(!target.has("old"));
+}
@@ -0,0 +1,150 @@
+mod common;
+
+use ndata::data::Data;
+use ndata::dataobject::DataObject;
+use ndata::dataarray::DataArray;
+
+/// Returns an array holding an int and a new object, and the data_ref of the object.
+fn array_with_object() -> (DataArray, usize) {
+  let mut a = DataArray::new();
+  a.push_int(1);
+  let o = DataObject::new();
+  let data_ref = o.data_ref;
+  a.push_object(o);
+  (a, data_ref)
+}
+
+#[test]
+fn drain_range_keeps_removed_objects_alive() {
+  let _guard = common::setup();
+  let (mut a, data_ref) = array_with_object();
+  let removed = a.drain_range(0, 2);
+  assert_eq!(a.len(), 0);
+  ndata::gc();
+  assert!(common::object_exists(data_ref));
+  assert_eq!(removed[1].object().data_ref, data_ref);
+  drop(removed);
+  ndata::gc();
+  assert!(!common::object_exists(data_ref));
+}
+
+#[test]
+fn owned_data_clones_hold_their_own_reference() {
+  let _guard = common::setup();
+  let (mut a, data_ref) = array_with_object();
+  let removed = a.drain_range(1, 2).pop().unwrap();
+  let copy = removed.clone();
+  drop(removed);
+  ndata::gc();
+  assert!(common::object_exists(data_ref));
+  assert!(matches!(*copy, Data::DObject(r) if r == data_ref));
+  drop(copy);
+  ndata::gc();
+  assert!(!common::object_exists(data_ref));
+}
+
+#[test]
+fn pop_front_and_pop_back_keep_removed_objects_alive() {
+  let _guard = common::setup();
+  let (mut a, back_ref) = array_with_object();
+  let front = DataObject::new();
+  let front_ref = front.data_ref;
+  a.push_front(Data::DObject(front_ref));
+  drop(front);
+  let back = a.pop_back().unwrap();
+  let front = a.pop_front().unwrap();
+  assert_eq!(a.len(), 1);
+  ndata::gc();
+  assert!(common::object_exists(back_ref));
+  assert!(common::object_exists(front_ref));
+  assert_eq!(front.object().data_ref, front_ref);
+  drop((front, back));
+  ndata::gc();
+  assert!(!common::object_exists(back_ref));
+  assert!(!common::object_exists(front_ref));
+  assert!(a.pop_back().is_some());
+  assert!(a.pop_front().is_none());
+}
+
+#[test]
+fn swap_remove_keeps_removed_objects_alive() {
+  let _guard = common::setup();
+  let (mut a, data_ref) = array_with_object();
+  a.push_int(2);
+  let removed = a.swap_remove(1);
+  assert_eq!(a.get_int(1), 2);
+  assert!(a.try_swap_remove(5).is_err());
+  ndata::gc();
+  assert!(common::object_exists(data_ref));
+  assert_eq!(removed.object().data_ref, data_ref);
+  drop(removed);
+  ndata::gc();
+  assert!(!common::object_exists(data_ref));
+}
+
+#[test]
+fn retain_releases_removed_objects() {
+  let _guard = common::setup();
+  let (mut a, data_ref) = array_with_object();
+  let mut inner = DataArray::new();
+  inner.push_object(DataObject::new());
+  a.push_array(inner);
+  let before = common::heap_counts();
+  let removed = a.retain(|d| d.is_int());
+  assert_eq!(removed, 2);
+  assert_eq!(a.len(), 1);
+  assert_eq!(a.get_int(0), 1);
+  assert_eq!(common::heap_counts(), (before.0 - 2, before.1 - 1));
+  assert!(!common::object_exists(data_ref));
+}
+
+#[test]
+fn slice_keeps_nested_objects_alive() {
+  let _guard = common::setup();
+  let (a, data_ref) = array_with_object();
+  let s = a.slice(1, 5);
+  drop(a);
+  ndata::gc();
+  assert_eq!(s.len(), 1);
+  assert!(common::object_exists(data_ref));
+  assert_eq!(s.get_object(0).data_ref, data_ref);
+  drop(s);
+  ndata::gc();
+  assert!(!common::object_exists(data_ref));
+}
+
+#[test]
+fn splice_keeps_removed_objects_alive() {
+  let _guard = common::setup();
+  let (mut a, data_ref) = array_with_object();
+  let removed = a.splice(1, 1, vec![Data::DInt(2), Data::DInt(3)]);
+  assert_eq!(a.len(), 3);
+  assert_eq!(a.get_int(2), 3);
+  ndata::gc();
+  assert!(common::object_exists(data_ref));
+  assert_eq!(removed.len(), 1);
+  assert_eq!(removed[0].object().data_ref, data_ref);
+  drop(removed);
+  ndata::gc();
+  assert!(!common::object_exists(data_ref));
+}
+
+#[test]
+fn take_keeps_removed_objects_alive() {
+  let _guard = common::setup();
+  let mut o = DataObject::new();
+  let inner = DataObject::new();
+  let data_ref = inner.data_ref;
+  o.put_object("inner", inner);
+  o.put_int("int", 1);
+  let taken = o.take("inner").unwrap();
+  assert!(!o.has("inner"));
+  assert!(o.take("inner").is_none());
+  assert_eq!(o.take("int").unwrap().int(), 1);
+  ndata::gc();
+  assert!(common::object_exists(data_ref));
+  assert_eq!(taken.object().data_ref, data_ref);
+  drop(taken);
+  ndata::gc();
+  assert!(!common::object_exists(data_ref));
+}
@@ -0,0 +1,49 @@
+use std::mem::MaybeUninit;
+use std::rc::Rc;
+use ndata::heap::Heap;
+use ndata::usizemap::UsizeMap;
+
+fn region(bytes:usize) -> &'static mut [MaybeUninit<u8>] {
+  Box::leak(vec![MaybeUninit::uninit(); bytes].into_boxed_slice())
+}
+
+#[test]
+fn heap_stores_instances_in_the_region() {
+  let r = region(Heap::<Vec<u8>>::region_size(4));
+  let (start, end) = (r.as_ptr() as usize, r.as_ptr() as usize + r.len());
+  let mut heap = Heap::<Vec<u8>>::in_region(r);
+  let refs:Vec<usize> = (0..4).map(|i| heap.push(vec![i])).collect();
+  for (i, x) in refs.iter().enumerate() {
+    let p = heap.get(*x) as *mut Vec<u8> as usize;
+    assert!(p >= start && p < end);
+    assert_eq!(heap.get(*x), &vec![i as u8]);
+  }
+  heap.decr(refs[1]);
+  assert_eq!(heap.keys(), vec![0, 2, 3]);
+  assert_eq!(heap.push(vec![9]), refs[1]);
+}
+
+#[test]
+#[should_panic(expected = "region is full")]
+fn full_region_panics() {
+  let mut map = UsizeMap::<u64>::in_region(region(UsizeMap::<u64>::region_size(2)));
+  map.insert(1);
+  map.insert(2);
+  map.insert(3);
+}
+
+#[test]
+fn region_values_are_dropped_with_the_map() {
+  let rc = Rc::new(());
+  let mut map = UsizeMap::<Rc<()>>::in_region(region(UsizeMap::<Rc<()>>::region_size(3)));
+  map.insert(rc.clone());
+  map.insert(rc.clone());
+  let i = map.insert(rc.clone());
+  map.remove(i);
+  assert_eq!(Rc::strong_count(&rc), 3);
+  assert_eq!(map.drain().count(), 2);
+  assert_eq!(Rc::strong_count(&rc), 1);
+  map.insert(rc.clone());
+  drop(map);
+  assert_eq!(Rc::strong_count(&rc), 1);
+}
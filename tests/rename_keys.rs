@@ -0,0 +1,29 @@
+mod common;
+
+use ndata::dataobject::DataObject;
+
+#[test]
+fn colliding_renames_keep_the_last_key_in_sorted_order() {
+  let _guard = common::setup();
+  let mut o = DataObject::new();
+  for i in 0..20 {
+    o.put_int(&format!("k{}", i), i);
+  }
+  o.rename_keys(|_k| "x".to_string());
+  assert_eq!(o.clone().keys(), vec!["x".to_string()]);
+  assert_eq!(o.get_int("x"), 9);
+}
+
+#[test]
+fn colliding_renames_release_discarded_values() {
+  let _guard = common::setup();
+  let mut o = DataObject::new();
+  o.put_object("a", DataObject::new());
+  o.put_int("b", 2);
+  o.put_int("c", 3);
+  let before = common::heap_counts();
+  o.rename_keys(|k| if k == "c" { k.to_string() } else { "c".to_string() });
+  assert_eq!(o.clone().keys(), vec!["c".to_string()]);
+  assert_eq!(o.get_int("c"), 3);
+  assert_eq!(common::heap_counts(), (before.0 - 1, before.1));
+}
@@ -0,0 +1,32 @@
+mod common;
+
+use ndata::dataobject::DataObject;
+use ndata::dataarray::DataArray;
+
+/// The steps share the global heaps, so they run in a single test
+#[test]
+fn reset_replaces_globals_with_an_empty_object() {
+  let _guard = common::setup_with_globals();
+  let mut g = ndata::globals();
+  g.put_int("a", 1);
+  let o = DataObject::new();
+  let data_ref = o.data_ref;
+  core::mem::forget(o);
+  core::mem::forget(DataArray::new());
+  core::mem::forget(g);
+  assert!(common::object_exists(data_ref));
+  assert!(ndata::reset());
+  let mut g = ndata::globals();
+  assert!(g.objects().is_empty());
+  assert_eq!(common::heap_counts(), (1, 0));
+  assert!(common::object_exists(g.data_ref));
+  g.put_int("b", 2);
+  drop(g);
+  ndata::gc();
+  assert_eq!(ndata::globals().get_int("b"), 2);
+  assert!(ndata::reset());
+  assert!(ndata::reset());
+  assert!(ndata::globals().objects().is_empty());
+  assert_eq!(common::heap_counts(), (1, 0));
+  assert!(common::object_exists(ndata::globals().data_ref));
+}
@@ -0,0 +1,31 @@
+use std::time::Duration;
+use std::time::Instant;
+
+use ndata::sharedmutex::ContentionPolicy;
+use ndata::sharedmutex::SharedMutex;
+
+#[test]
+fn lock_timeout_gives_up_while_the_lock_is_held() {
+  for policy in [ContentionPolicy::Spin, ContentionPolicy::Backoff, ContentionPolicy::Yield] {
+    let mut m = SharedMutex::new();
+    m.set(7);
+    m.set_contention_policy(policy);
+    let guard = m.lock();
+    let start = Instant::now();
+    assert!(m.lock_timeout(1000).is_none());
+    assert!(start.elapsed() < Duration::from_secs(1), "{:?} took {:?}", policy, start.elapsed());
+    assert!(m.try_lock().is_none());
+    drop(guard);
+    assert_eq!(*m.lock_timeout(0).unwrap(), 7);
+  }
+}
+
+#[test]
+fn lock_backs_off_by_default() {
+  let m = SharedMutex::<u8>::new();
+  assert_eq!(m.contention_policy(), ContentionPolicy::Backoff);
+  assert_eq!(SharedMutex::<u8>::default().contention_policy(), ContentionPolicy::Backoff);
+  assert_eq!(ContentionPolicy::default(), ContentionPolicy::Backoff);
+  m.set_contention_policy(ContentionPolicy::Spin);
+  assert_eq!(m.contention_policy(), ContentionPolicy::Spin);
+}
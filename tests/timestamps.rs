@@ -0,0 +1,34 @@
+mod common;
+
+use ndata::data::Data;
+use ndata::dataobject::*;
+use ndata::dataarray::DataArray;
+
+#[test]
+fn timestamps_serialize_as_rfc3339_when_enabled() {
+  let _guard = common::setup();
+  let mut o = DataObject::new();
+  o.put_timestamp("t", 86400, 5);
+  let mut a = DataArray::new();
+  a.push_property(o.get_property("t"));
+  set_timestamps_as_rfc3339(true);
+  let (s, t) = (o.to_string(), a.to_string());
+  set_timestamps_as_rfc3339(false);
+  assert_eq!(s, r#"{"t":"1970-01-02T00:00:00.000000005Z"}"#);
+  assert_eq!(t, r#"["1970-01-02T00:00:00.000000005Z"]"#);
+  assert!(o.to_string().contains(TIMESTAMP_TAG));
+}
+
+#[test]
+fn as_timestamp_rejects_out_of_range_nanos() {
+  let _guard = common::setup();
+  let mut ts = DataObject::new();
+  ts.put_int("secs", 1);
+  ts.put_int("nanos", 1_000_000_000);
+  let o = DataObject::tagged(TIMESTAMP_TAG, Data::DObject(ts.data_ref));
+  assert_eq!(o.as_timestamp(), None);
+  ts.put_int("nanos", -1);
+  assert_eq!(o.as_timestamp(), None);
+  ts.put_int("nanos", 999_999_999);
+  assert_eq!(o.as_timestamp(), Some((1, 999_999_999)));
+}
@@ -0,0 +1,96 @@
+mod common;
+
+use ndata::data::Data;
+use ndata::dataobject::DataObject;
+use ndata::dataarray::*;
+
+#[test]
+fn typed_array_stays_packed_for_matching_values() {
+  let _guard = common::setup();
+  let mut a = DataArray::new_typed(ElemType::Int);
+  for i in [3, 1, 2] {
+    a.push_int(i);
+  }
+  a.push_property(Data::DInt(4));
+  a.sort_ints();
+  assert_eq!(a.elem_type(), Some(ElemType::Int));
+  assert_eq!(&*a.as_i64_slice().unwrap(), &[1, 2, 3, 4]);
+  assert!(a.as_f64_slice().is_none());
+  assert_eq!(a.len(), 4);
+  assert_eq!(a.get_int(1), 2);
+  assert_eq!(a.last().map(|d| d.int()), Some(4));
+  assert_eq!(a.to_int_vec(), Some(vec![1, 2, 3, 4]));
+  assert_eq!(a.to_float_vec(), Some(vec![1.0, 2.0, 3.0, 4.0]));
+  assert_eq!(a.is_homogeneous(), Some("int"));
+  assert_eq!(a.to_string(), "[1,2,3,4]");
+  assert_eq!(a.elem_type(), Some(ElemType::Int));
+}
+
+#[test]
+fn typed_array_falls_back_on_mixed_values() {
+  let _guard = common::setup();
+  let mut a = DataArray::new_typed(ElemType::Float);
+  a.push_float(1.5);
+  assert_eq!(&*a.as_f64_slice().unwrap(), &[1.5]);
+  a.push_int(2);
+  assert_eq!(a.elem_type(), None);
+  assert!(a.as_f64_slice().is_none());
+  a.push_string("x");
+  assert_eq!(a.to_string(), r#"[1.5,2,"x"]"#);
+}
+
+#[test]
+fn typed_array_is_collected_and_smaller() {
+  let _guard = common::setup();
+  let mut packed = DataArray::new_typed(ElemType::Int);
+  let mut plain = DataArray::new();
+  for i in 0..1000 {
+    packed.push_int(i);
+    plain.push_int(i);
+  }
+  assert!(Data::DArray(packed.data_ref).heap_size() < Data::DArray(plain.data_ref).heap_size());
+  let mut o = DataObject::new();
+  o.put_array("a", packed.clone());
+  let data_ref = packed.data_ref;
+  drop(packed);
+  ndata::gc();
+  assert_eq!(o.get_array("a").as_i64_slice().unwrap().len(), 1000);
+  o.remove_property("a");
+  ndata::gc();
+  assert!(ndata::dataarray::aheap().lock().try_get(data_ref).is_none());
+}
+
+#[test]
+fn typed_array_stays_packed_when_searched_and_edited() {
+  let _guard = common::setup();
+  let mut a = DataArray::new_typed(ElemType::Int);
+  for i in [5, 1, 4, 2] {
+    a.push_int(i);
+  }
+  assert!(a.contains(&Data::DInt(1)));
+  assert_eq!(a.index_of(Data::DInt(4)), 2);
+  assert!(!a.push_unique(Data::DInt(5)));
+  assert!(a.push_unique(Data::DInt(3)));
+  a.put_int(0, 0);
+  a.insert_at(1, Data::DInt(9));
+  a.push_front(Data::DInt(8));
+  assert_eq!(a.to_int_vec(), Some(vec![8, 0, 9, 1, 4, 2, 3]));
+  assert_eq!(a.pop_front().unwrap().int(), 8);
+  assert_eq!(a.pop_back().unwrap().int(), 3);
+  assert_eq!(a.swap_remove(1).int(), 9);
+  a.remove_property(0);
+  a.swap(0, 1);
+  a.reverse();
+  assert!(a.remove_data(Data::DInt(4)));
+  a.sort_ints();
+  assert_eq!(a.binary_search_by(|d| d.int().cmp(&2)), Ok(1));
+  let s = a.slice(0, 5);
+  assert_eq!(s.elem_type(), Some(ElemType::Int));
+  assert_eq!(&*s.as_i64_slice().unwrap(), &[1, 2]);
+  a.truncate(1);
+  assert_eq!(a.drain_range(0, 1)[0].int(), 1);
+  a.push_int(7);
+  a.clear();
+  assert_eq!(a.len(), 0);
+  assert_eq!(a.elem_type(), Some(ElemType::Int));
+}
@@ -0,0 +1,24 @@
+use ndata::usizemap::UsizeMap;
+
+#[test]
+fn collect_assigns_keys_in_order() {
+  let mut map:UsizeMap<u32> = (10..15).collect();
+  assert_eq!(map.len(), 5);
+  assert_eq!(map.keys(), vec![0, 1, 2, 3, 4]);
+  assert_eq!(map.get_mut(3), Some(&mut 13));
+  let empty:UsizeMap<u32> = (0..0).collect();
+  assert_eq!(empty.len(), 0);
+  assert!(empty.keys().is_empty());
+}
+
+#[test]
+fn extend_fills_removed_keys_first() {
+  let mut map:UsizeMap<u32> = (0..5).collect();
+  map.remove(1);
+  map.remove(3);
+  assert_eq!(map.keys(), vec![0, 2, 4]);
+  map.extend(5..9);
+  assert_eq!(map.len(), 7);
+  assert_eq!(map.keys(), (0..7).collect::<Vec<usize>>());
+  assert_eq!(map.get_mut(6), Some(&mut 8));
+}
@@ -0,0 +1,38 @@
+#![cfg(not(feature="no_std_support"))]
+
+mod common;
+
+use ndata::dataobject::DataObject;
+use ndata::dataarray::DataArray;
+use ndata::databytes::DataBytes;
+
+fn nested() -> DataObject {
+  let mut o = DataObject::new();
+  o.put_int("i", 1);
+  o.put_float("f", 2.5);
+  o.put_float("nan", f64::NAN);
+  o.put_string("s", "a \"quoted\" string");
+  o.put_null("n");
+  o.put_bytes("b", DataBytes::from_bytes(&vec![0, 1, 254]));
+  let mut a = DataArray::new();
+  a.push_boolean(true);
+  let mut inner = DataObject::new();
+  inner.put_int("x", 3);
+  inner.put_timestamp("t", 0, 0);
+  a.push_object(inner);
+  o.put_array("a", a);
+  o
+}
+
+#[test]
+fn write_json_matches_to_string() {
+  let _guard = common::setup();
+  let o = nested();
+  let mut v = Vec::new();
+  o.write_json(&mut v).unwrap();
+  assert_eq!(String::from_utf8(v).unwrap(), o.to_string());
+  let a = o.get_array("a");
+  let mut v = Vec::new();
+  a.write_json(&mut v).unwrap();
+  assert_eq!(String::from_utf8(v).unwrap(), a.to_string());
+}